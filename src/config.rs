@@ -0,0 +1,63 @@
+// Settings that depend on the machine are given on the command line, like `--cell-size 10`, so they can be changed without rebuilding.
+// They are read while the app is built, before anything uses them.
+
+use crate::prelude::*;
+use std::str::FromStr;
+
+app!(|app| {
+    let arguments: Vec<String> = std::env::args().collect();
+
+    let mut grid_settings = GridSettings::default();
+    if let Some(cell_size) = positive(&arguments, "--cell-size") {
+        grid_settings.cell_size = cell_size;
+    }
+    app.insert_resource(grid_settings);
+
+    let mut fluid_timestep = FluidTimestep::default();
+    if let Some(rate) = positive(&arguments, "--fluid-rate") {
+        fluid_timestep.rate = rate;
+    }
+    if let Some(substeps) = argument(&arguments, "--fluid-substeps") {
+        fluid_timestep.substeps = substeps;
+    }
+    app.insert_resource(fluid_timestep);
+
+    let mut pressure_solver = PressureSolver::default();
+    if let Some(tolerance) = positive(&arguments, "--pressure-tolerance") {
+        pressure_solver.tolerance = tolerance;
+    }
+    if let Some(max_iterations) = argument(&arguments, "--pressure-iterations") {
+        pressure_solver.max_iterations = max_iterations;
+    }
+    app.insert_resource(pressure_solver);
+});
+
+/// Finds the value after a flag.
+/// Returns none if the flag isn't there, or its value can't be read.
+fn argument<T: FromStr>(arguments: &[String], flag: &str) -> Option<T> {
+    let index = arguments.iter().position(|argument| argument == flag)?;
+
+    let Some(value) = arguments.get(index + 1) else {
+        error!("{flag} needs a value after it.");
+        return None;
+    };
+
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            error!("{flag} can't be {value}.");
+            None
+        }
+    }
+}
+
+/// The same as argument, for sizes and rates, which have to be above 0.
+fn positive(arguments: &[String], flag: &str) -> Option<f32> {
+    let value: f32 = argument(arguments, flag)?;
+    if value > 0. && value.is_finite() {
+        Some(value)
+    } else {
+        error!("{flag} has to be above 0.");
+        None
+    }
+}
@@ -5,6 +5,7 @@ pub mod prelude {
 }
 
 /// The world space cursor translation and the camera it is on.
+/// World space is shared by every window, so the translation doesn't depend on which window the cursor is on.
 #[init]
 #[derive(Resource, Default)]
 pub struct CursorTranslation(pub Option<InnerCursorTranslation>);
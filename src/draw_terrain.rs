@@ -11,7 +11,17 @@ struct DrawSettings {
     // Squishes the brush strokes together. I'm not sure how.
     squish: f32,
     collision: bool,
+    material: TerrainMaterial,
 }
+
+/// What the terrain is made of.
+/// Each material's button has the material as a component.
+#[derive(Component, Clone, Copy, PartialEq)]
+enum TerrainMaterial {
+    Rock,
+    ThermalVent,
+}
+
 #[system(Startup)]
 fn draw_settings(asset_server: Res<AssetServer>, mut commands: Commands) {
     commands.insert_resource(DrawSettings {
@@ -21,6 +31,7 @@ fn draw_settings(asset_server: Res<AssetServer>, mut commands: Commands) {
         depth: 0.,
         squish: 0.3,
         collision: true,
+        material: TerrainMaterial::Rock,
     });
 }
 
@@ -38,7 +49,10 @@ fn draw_terrain(
     settings: Res<DrawSettings>,
     images: Res<Assets<Image>>,
     tool_bar_hovered: Res<ToolBarHovered>,
+    material_buttons: Query<&Interaction, With<TerrainMaterial>>,
+    grids: Query<&Grid>,
     mut set_solid: EventWriter<SetSolid>,
+    mut set_thermal_vent: EventWriter<SetThermalVent>,
 ) {
     if !matches!(*tool, Tool::Draw) {
         return;
@@ -48,6 +62,13 @@ fn draw_terrain(
         return;
     }
 
+    if material_buttons
+        .iter()
+        .any(|interaction| !matches!(interaction, Interaction::None))
+    {
+        return;
+    }
+
     if !actions.pressed(&Action::Use) {
         return;
     }
@@ -62,18 +83,37 @@ fn draw_terrain(
 
     let size = image.size_f32();
 
+    let window = cursor_translation.window;
+    let mut paint = |translation: Vec2| match settings.material {
+        TerrainMaterial::Rock => {
+            set_solid.send(SetSolid {
+                window,
+                translation,
+                colour: Srgba::BLACK,
+            });
+        }
+        TerrainMaterial::ThermalVent => {
+            set_thermal_vent.send(SetThermalVent {
+                window,
+                translation,
+                temperature: ThermalVent::DEFAULT_TEMPERATURE,
+            });
+        }
+    };
+
     // If we just clicked somewhere, we spawn a terrain point, and set the previous translation to be at the cursor.
     // If we don't do this, then we get a cool straight line effect.
     if actions.just_pressed(&Action::Use) {
-        set_solid.send(SetSolid {
-            window: cursor_translation.window,
-            translation: cursor_translation.translation,
-            colour: Srgba::BLACK,
-        });
+        paint(cursor_translation.translation);
         previous_translation.0 = cursor_translation.translation;
     }
 
-    let radius_average_squished = (size.x + size.y) / 2. * settings.scale * settings.squish;
+    let mut radius_average_squished = (size.x + size.y) / 2. * settings.scale * settings.squish;
+
+    // Terrain is painted into cells, so stepping further than 1 cell would leave gaps.
+    if let Ok(grid) = grids.get(window) {
+        radius_average_squished = radius_average_squished.min(grid.cell_size());
+    }
 
     // Create points until we reach the cursor translation.
     loop {
@@ -93,11 +133,7 @@ fn draw_terrain(
         // Move the previous translation in the correct direction.
         previous_translation.0 += direction * radius_average_squished;
 
-        set_solid.send(SetSolid {
-            window: cursor_translation.window,
-            translation: previous_translation.0,
-            colour: Srgba::BLACK,
-        });
+        paint(previous_translation.0);
     }
 }
 
@@ -127,6 +163,7 @@ struct Root;
 #[system(Update)]
 fn ui(
     cursor_translation: Res<CursorTranslation>,
+    asset_server: Res<AssetServer>,
     mut commands: Commands,
     mut finished: Local<bool>,
 ) {
@@ -140,7 +177,7 @@ fn ui(
 
     *finished = true;
 
-    let mut _root = commands.spawn((Root, TargetCamera(cursor_translation.window), Node {
+    let mut root = commands.spawn((Root, TargetCamera(cursor_translation.window), Node {
         display: Display::Flex,
         flex_direction: FlexDirection::Column,
         align_items: AlignItems::Start,
@@ -150,7 +187,27 @@ fn ui(
         ..default()
     }));
 
-    // TODO: Allow editing of settings via ui.
+    [
+        ("Rock", TerrainMaterial::Rock),
+        ("Thermal vent", TerrainMaterial::ThermalVent),
+    ]
+    .into_iter()
+    .for_each(|(text, material)| {
+        root.with_child((text_button(text, &asset_server), material));
+    });
+
+    // TODO: Allow editing of the other settings via ui.
+}
+
+#[system(Update)]
+fn material_buttons(
+    settings: ResMut<DrawSettings>,
+    buttons: Query<(&Interaction, &mut BackgroundColor, &TerrainMaterial), With<Button>>,
+) {
+    selection_buttons(
+        settings.map_unchanged(|settings| &mut settings.material),
+        buttons,
+    );
 }
 
 #[system(Update)]
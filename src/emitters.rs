@@ -0,0 +1,232 @@
+use crate::prelude::*;
+
+#[derive(Resource)]
+struct EmitterSettings {
+    kind: EmitterKind,
+    radius: f32,
+}
+
+#[system(Startup)]
+fn emitter_settings(mut commands: Commands) {
+    commands.insert_resource(EmitterSettings {
+        kind: EmitterKind::Emitter,
+        radius: 30.,
+    });
+}
+
+/// What the tool places.
+/// Each kind's button has the kind as a component.
+#[derive(Component, Clone, Copy, PartialEq)]
+enum EmitterKind {
+    Emitter,
+    Drain,
+}
+
+/// Marks emitters and drains that were placed by the tool, so that the tool can delete them.
+#[derive(Component)]
+struct Placed {
+    radius: f32,
+}
+
+/// Click to place, and drag before letting go to aim emitters.
+/// Clicking on something that was already placed deletes it.
+#[system(Update)]
+fn place(
+    tool: Res<Tool>,
+    actions: Actions,
+    cursor_translation: Res<CursorTranslation>,
+    tool_bar_hovered: Res<ToolBarHovered>,
+    // Hidden buttons are never interacted with, so these are only this tool's buttons and the tool bar's.
+    buttons: Query<&Interaction, With<Button>>,
+    settings: Res<EmitterSettings>,
+    placed: Query<(Entity, &Placed, &Transform)>,
+    mut emitters: Query<&mut Emitter>,
+    mut aiming: Local<Option<Entity>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    if !matches!(*tool, Tool::Emitters) {
+        *aiming = None;
+        return;
+    }
+
+    let Some(cursor_translation) = &cursor_translation.0 else {
+        return;
+    };
+    let translation = cursor_translation.translation;
+
+    // While the button is held, the emitter points from where it was placed towards the cursor.
+    if let Some(aiming_entity) = *aiming {
+        if !actions.pressed(&Action::Use) {
+            *aiming = None;
+            return;
+        }
+
+        let (Ok((_, _, transform)), Ok(mut emitter)) =
+            (placed.get(aiming_entity), emitters.get_mut(aiming_entity))
+        else {
+            *aiming = None;
+            return;
+        };
+
+        emitter.velocity = (translation - transform.translation.xy()) * 2.;
+        return;
+    }
+
+    if tool_bar_hovered.0
+        || buttons
+            .iter()
+            .any(|interaction| !matches!(interaction, Interaction::None))
+    {
+        return;
+    }
+
+    if !actions.just_pressed(&Action::Use) {
+        return;
+    }
+
+    if let Some((entity, _, _)) = placed.iter().find(|(_, placed, transform)| {
+        transform.translation.xy().distance(translation) <= placed.radius
+    }) {
+        commands.entity(entity).despawn_recursive();
+        return;
+    }
+
+    let (colour, mut placed) = match settings.kind {
+        EmitterKind::Emitter => {
+            let placed = commands.spawn(Emitter {
+                radius: settings.radius,
+                velocity: Vec2::ZERO,
+            });
+            *aiming = Some(placed.id());
+            (Srgba::new(0.4, 0.7, 1., 0.6), placed)
+        }
+        EmitterKind::Drain => (
+            Srgba::new(0.1, 0.1, 0.1, 0.6),
+            commands.spawn(Drain {
+                radius: settings.radius,
+            }),
+        ),
+    };
+
+    placed.insert((
+        Placed {
+            radius: settings.radius,
+        },
+        Transform::from_translation(translation.extend(0.01)),
+        Sprite {
+            image: asset_server.load("brushes/circle.png"),
+            color: colour.into(),
+            custom_size: Some(Vec2::splat(settings.radius * 2.)),
+            ..default()
+        },
+    ));
+}
+
+/// Shows which way each emitter is pointing.
+#[system(Update)]
+fn debug(tool: Res<Tool>, emitters: Query<(&Emitter, &Transform)>, mut gizmos: Gizmos) {
+    if !matches!(*tool, Tool::Emitters) {
+        return;
+    }
+
+    emitters.iter().for_each(|(emitter, transform)| {
+        let translation = transform.translation.xy();
+        gizmos.arrow_2d(
+            translation,
+            translation + emitter.velocity / 2.,
+            Srgba::WHITE,
+        );
+    });
+}
+
+#[derive(Component)]
+struct Root;
+
+/// Changes the radius of things that are placed after.
+#[derive(Component)]
+struct RadiusButton(f32);
+
+#[system(Update)]
+fn ui(
+    cursor_translation: Res<CursorTranslation>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    mut finished: Local<bool>,
+) {
+    if *finished {
+        return;
+    }
+
+    let Some(cursor_translation) = &cursor_translation.0 else {
+        return;
+    };
+
+    *finished = true;
+
+    let mut root = commands.spawn((Root, TargetCamera(cursor_translation.window), Node {
+        display: Display::Flex,
+        flex_direction: FlexDirection::Column,
+        align_items: AlignItems::Start,
+        justify_content: JustifyContent::Center,
+        width: Val::Percent(100.),
+        height: Val::Percent(100.),
+        ..default()
+    }));
+
+    [
+        ("Emitter", EmitterKind::Emitter),
+        ("Drain", EmitterKind::Drain),
+    ]
+    .into_iter()
+    .for_each(|(text, kind)| {
+        root.with_child((text_button(text, &asset_server), kind));
+    });
+
+    [("Bigger", 10.), ("Smaller", -10.)]
+        .into_iter()
+        .for_each(|(text, change)| {
+            root.with_child((text_button(text, &asset_server), RadiusButton(change)));
+        });
+}
+
+#[system(Update)]
+fn kind_buttons(
+    settings: ResMut<EmitterSettings>,
+    buttons: Query<(&Interaction, &mut BackgroundColor, &EmitterKind), With<Button>>,
+) {
+    selection_buttons(
+        settings.map_unchanged(|settings| &mut settings.kind),
+        buttons,
+    );
+}
+
+#[system(Update)]
+fn radius_buttons(
+    mut settings: ResMut<EmitterSettings>,
+    mut buttons: Query<(Ref<Interaction>, &mut BackgroundColor, &RadiusButton), With<Button>>,
+) {
+    buttons
+        .iter_mut()
+        .for_each(|(interaction, mut colour, radius_button)| {
+            // Only changed once per press, not every frame it is held.
+            if *interaction == Interaction::Pressed && interaction.is_changed() {
+                settings.radius = (settings.radius + radius_button.0).max(10.);
+            }
+
+            colour.0 = button_colour(&interaction, false);
+        });
+}
+
+#[system(Update)]
+fn ui_visibility(tool: Res<Tool>, visibility: Option<Single<&mut Visibility, With<Root>>>) {
+    let Some(mut visibility) = visibility else {
+        return;
+    };
+
+    if matches!(*tool, Tool::Emitters) {
+        **visibility = Visibility::Visible;
+    } else {
+        **visibility = Visibility::Hidden;
+    }
+}
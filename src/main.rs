@@ -16,13 +16,13 @@ mod prelude {
     pub use foldhash::HashMap;
     pub use leafwing_input_manager::prelude::*;
 }
-use std::time::Duration;
-
 use prelude::*;
 
 mod actions;
+mod config;
 mod cursor_translation;
 mod draw_terrain;
+mod emitters;
 mod interactable;
 mod physics;
 mod tools;
@@ -33,12 +33,22 @@ mod water;
 mod windowing_linux;
 
 schedule! {
-    Update (
-        [run_every(Duration::from_secs_f32(1. / 30.))]
+    FluidUpdate (
         Fluid (
+            StorePrevious,
             VelocityDivergence,
             Solve,
+            Project,
+            Extrapolate,
+            GridToParticles,
+            Sph,
+            Advect,
+            ParticlesToGrid,
+            Redistance,
+            Diffuse,
+            Vorticity,
             Forces,
+            Boundaries,
         )
     )
 }
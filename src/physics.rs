@@ -1,8 +1,21 @@
 mod cell;
+mod coupling;
+mod dye;
+mod emitter;
 mod fluid;
 mod grid;
+mod liquid;
+mod particles;
 mod solid;
+mod sph;
+mod surface;
+mod thermal_vent;
+mod timestep;
 
 pub mod prelude {
-    pub use super::{cell::prelude::*, fluid::prelude::*, grid::prelude::*, solid::prelude::*};
+    pub use super::{
+        cell::prelude::*, dye::prelude::*, emitter::prelude::*, fluid::prelude::*,
+        grid::prelude::*, liquid::prelude::*, particles::prelude::*, solid::prelude::*,
+        sph::prelude::*, thermal_vent::prelude::*, timestep::prelude::*,
+    };
 }
@@ -7,20 +7,21 @@ pub mod prelude {
 /// A grid cell.
 /// I don't fully know how this is going to work.
 #[derive(Component)]
-#[require(Fluid)]
 pub struct Cell {
     pub grid: Entity,
 
+    /// The index of the cell in FluidCells.
     pub index: usize,
     pub translation: Vec2,
-
-    /// The 4 nearest cells.
-    /// Ordered top, left, right, bottom.
-    pub nearest_4: [Option<Entity>; 4],
 }
 
 impl Cell {
-    pub const SIZE: f32 = 30.;
+    /// The transform for a cell's mesh.
+    /// Cell meshes are 1 by 1 squares, so they are scaled up to the cell size.
+    pub fn transform(translation: Vec2, cell_size: f32) -> Transform {
+        Transform::from_translation(translation.extend(0.))
+            .with_scale(Vec3::new(cell_size, cell_size, 1.))
+    }
 }
 
 /// FIXME: Abandoned, for now. Consider fixing it and profiling it.
@@ -0,0 +1,137 @@
+use crate::prelude::*;
+
+/// How rigid bodies and the grid fluid affect each other.
+#[init]
+#[derive(Resource)]
+pub struct FluidCoupling {
+    /// The mass of a square unit of water, in the same units as ColliderDensity.
+    /// Colliders have a density of 1 by default, so they will sink, while anything lighter than this will float.
+    pub density: f32,
+    /// How strongly water drags bodies along with it.
+    pub drag: f32,
+    /// How quickly the water around a moving body takes on the body's velocity, per second.
+    /// At 20, the water closes about half the gap every 30th of a second, no matter how often the fluid is stepped.
+    pub feedback: f32,
+}
+
+impl Default for FluidCoupling {
+    fn default() -> Self {
+        Self {
+            density: 0.8,
+            drag: 20.,
+            feedback: 20.,
+        }
+    }
+}
+
+/// Pushes bodies up out of the water, and drags them along with the current.
+#[system(Update)]
+fn fluid_forces(
+    mut bodies: Query<
+        (
+            &RigidBody,
+            &mut LinearVelocity,
+            &ComputedMass,
+            &ColliderAabb,
+            &ColliderMassProperties,
+            &ColliderDensity,
+        ),
+        Without<Sensor>,
+    >,
+    fluid_sampler: FluidSampler,
+    fluid_coupling: Res<FluidCoupling>,
+    gravity: Res<Gravity>,
+    time: Res<Time>,
+) {
+    let time_delta_seconds = time.delta_secs();
+
+    bodies.iter_mut().for_each(
+        |(
+            rigid_body,
+            mut linear_velocity,
+            computed_mass,
+            collider_aabb,
+            collider_mass_properties,
+            collider_density,
+        )| {
+            if !rigid_body.is_dynamic() {
+                return;
+            }
+
+            let centre = (collider_aabb.min + collider_aabb.max) / 2.;
+            let size = collider_aabb.max - collider_aabb.min;
+
+            let (Some(level_set), Some(fluid_velocity)) = (
+                fluid_sampler.level_set(centre),
+                fluid_sampler.velocity(centre),
+            ) else {
+                return;
+            };
+
+            // How much of the body is underwater, assuming the surface is flat.
+            let submerged = (0.5 - level_set / size.y).clamp(0., 1.);
+            if submerged == 0. {
+                return;
+            }
+
+            let mass = computed_mass.value();
+            if mass <= 0. || collider_density.0 <= 0. {
+                return;
+            }
+
+            // The collider's own area, rather than its AABB's, so that round bodies don't push out the water in their corners.
+            let area = collider_mass_properties.mass / collider_density.0;
+
+            // Archimedes: the water pushes up with the weight of the water that was pushed out of the way.
+            let displaced_mass = fluid_coupling.density * area * submerged;
+            let buoyancy = -gravity.0 * displaced_mass / mass;
+
+            // Bigger bodies catch more of the current, heavier bodies are harder to move.
+            // Limited to 1, so the body never ends up going faster than the water.
+            let drag = (fluid_coupling.drag * (size.x + size.y) * submerged / mass
+                * time_delta_seconds)
+                .min(1.);
+
+            let velocity = linear_velocity.0 + buoyancy * time_delta_seconds;
+            linear_velocity.0 = velocity + (fluid_velocity - velocity) * drag;
+        },
+    );
+}
+
+/// Moving bodies push the water they are in, which is what makes a dropped rock splash.
+#[system(FluidUpdate::Fluid::Forces)]
+fn body_feedback(
+    bodies: Query<(&RigidBody, &LinearVelocity, &ColliderAabb), Without<Sensor>>,
+    grids: Query<&Grid>,
+    fluid_coupling: Res<FluidCoupling>,
+    mut fluid_cells: ResMut<FluidCells>,
+    fluid_time: Res<FluidTime>,
+) {
+    // Decaying the gap exponentially over time, rather than by a fixed amount each step, means that it doesn't depend on the step length.
+    let feedback = 1. - (-fluid_coupling.feedback * fluid_time.delta_secs()).exp();
+
+    bodies
+        .iter()
+        .for_each(|(rigid_body, linear_velocity, collider_aabb)| {
+            // Static bodies don't move, and are drawn into the grid as solids instead.
+            if rigid_body.is_static() {
+                return;
+            }
+
+            let rect = Rect::from_corners(collider_aabb.min, collider_aabb.max);
+
+            grids.iter().for_each(|grid| {
+                let first_cell = grid.cells_range().start;
+
+                grid.region().cells_in_rect(rect).for_each(|index| {
+                    let index = first_cell + index;
+                    if !fluid_cells.liquid(index) {
+                        return;
+                    }
+
+                    fluid_cells.velocity[index] =
+                        fluid_cells.velocity[index].lerp(linear_velocity.0, feedback);
+                });
+            });
+        });
+}
@@ -0,0 +1,192 @@
+use super::fluid::par_for_each_mut;
+use crate::prelude::*;
+use bevy::render::{
+    render_asset::RenderAssetUsages,
+    render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+pub mod prelude {
+    pub use super::AddDye;
+}
+
+/// Colours the fluid at a translation.
+#[init]
+#[derive(Event)]
+pub struct AddDye {
+    pub window: Entity,
+    pub translation: Vec2,
+    pub colour: LinearRgba,
+}
+
+#[system(Update)]
+fn add_dye(
+    mut add_dye: EventReader<AddDye>,
+    grids: Query<&Grid>,
+    mut fluid_cells: ResMut<FluidCells>,
+) {
+    add_dye.read().for_each(|add_dye| {
+        let Ok(grid) = grids.get(add_dye.window) else {
+            return;
+        };
+
+        let Some(index) = grid.index(add_dye.translation) else {
+            return;
+        };
+
+        if fluid_cells.solid[index] {
+            return;
+        }
+
+        fluid_cells.dye[index] = add_dye.colour;
+    });
+}
+
+/// Moves the dye along with the velocity, the same way the velocity is advected.
+#[system(FluidUpdate::Fluid::Advect)]
+fn advect(mut fluid_cells: ResMut<FluidCells>, grids: Query<&Grid>, fluid_time: Res<FluidTime>) {
+    let time_delta_seconds = fluid_time.delta_secs();
+
+    let mut advected_dye = std::mem::take(&mut fluid_cells.dye_update);
+    let all_grids: Vec<&Grid> = grids.iter().collect();
+
+    grids.iter().for_each(|grid| {
+        let range = grid.cells_range();
+        let first_cell = range.start;
+
+        par_for_each_mut(&mut advected_dye[range], |index, advected_dye| {
+            let index = first_cell + index;
+            if fluid_cells.solid[index] {
+                *advected_dye = LinearRgba::NONE;
+                return;
+            }
+
+            let previous_translation =
+                fluid_cells.translation[index] - fluid_cells.velocity[index] * time_delta_seconds;
+
+            *advected_dye =
+                fluid_cells.sample_across(&all_grids, grid, &fluid_cells.dye, previous_translation);
+        });
+    });
+
+    fluid_cells.dye_update = std::mem::replace(&mut fluid_cells.dye, advected_dye);
+}
+
+/// Keeps the dye from before the step, so that it can be interpolated when it is drawn.
+#[system(FluidUpdate::Fluid::StorePrevious)]
+fn store_previous(mut fluid_cells: ResMut<FluidCells>, fluid_time: Res<FluidTime>) {
+    // Only the first substep, as the dye is interpolated between whole steps.
+    if fluid_time.substep() != 0 {
+        return;
+    }
+
+    let FluidCells {
+        dye, previous_dye, ..
+    } = &mut *fluid_cells;
+    previous_dye.copy_from_slice(dye);
+}
+
+/// A sprite that shows the dye of every cell in a grid.
+/// Each pixel of the image is 1 cell.
+#[derive(Component)]
+struct DyeSprite {
+    grid: Entity,
+    image: Handle<Image>,
+}
+
+/// Creates a dye sprite for every new or rebuilt grid, replacing the old one.
+#[system(Update)]
+fn create_dye_sprites(
+    grids: Query<(Entity, &Grid, &RenderLayers), Changed<Grid>>,
+    all_grids: Query<(), With<Grid>>,
+    dye_sprites: Query<(Entity, &DyeSprite)>,
+    mut images: ResMut<Assets<Image>>,
+    mut commands: Commands,
+) {
+    // Sprites are removed if their grid has been rebuilt or no longer exists.
+    dye_sprites
+        .iter()
+        .for_each(|(dye_sprite_entity, dye_sprite)| {
+            if grids.contains(dye_sprite.grid) || !all_grids.contains(dye_sprite.grid) {
+                images.remove(&dye_sprite.image);
+                commands.entity(dye_sprite_entity).despawn();
+            }
+        });
+
+    grids.iter().for_each(|(grid_entity, grid, render_layers)| {
+        let size = grid.region().size;
+
+        let image = images.add(Image::new_fill(
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0; 4],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::all(),
+        ));
+
+        let rect = grid.rect();
+
+        commands.spawn((
+            DyeSprite {
+                grid: grid_entity,
+                image: image.clone(),
+            },
+            Sprite {
+                image,
+                custom_size: Some(rect.size()),
+                ..default()
+            },
+            // Just behind the terrain.
+            Transform::from_translation(rect.center().extend(-0.01)),
+            render_layers.clone(),
+        ));
+    });
+}
+
+/// The colour of water with no dye in it.
+const WATER_COLOUR: LinearRgba = LinearRgba::new(0., 0.1, 0.4, 0.15);
+
+/// Shows the dye in the water, and leaves the air clear.
+#[system(Update)]
+fn render(
+    dye_sprites: Query<&DyeSprite>,
+    grids: Query<&Grid>,
+    fluid_cells: Res<FluidCells>,
+    fluid_time: Res<FluidTime>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let overstep_fraction = fluid_time.overstep_fraction();
+
+    dye_sprites.iter().for_each(|dye_sprite| {
+        let Ok(grid) = grids.get(dye_sprite.grid) else {
+            return;
+        };
+
+        let Some(image) = images.get_mut(&dye_sprite.image) else {
+            return;
+        };
+
+        let size = grid.region().size;
+        let first_cell = grid.cells_range().start;
+
+        (0..grid.region().len()).for_each(|index| {
+            // The grid starts at the bottom, but the image starts at the top.
+            let x = index % size.x as usize;
+            let y = size.y as usize - 1 - index / size.x as usize;
+            let pixel = (y * size.x as usize + x) * 4;
+
+            let colour = if fluid_cells.liquid(first_cell + index) {
+                let dye = fluid_cells.previous_dye[first_cell + index]
+                    .mix(&fluid_cells.dye[first_cell + index], overstep_fraction);
+                WATER_COLOUR.mix(&dye, dye.alpha)
+            } else {
+                LinearRgba::NONE
+            };
+
+            image.data[pixel..pixel + 4].copy_from_slice(&Srgba::from(colour).to_u8_array());
+        });
+    });
+}
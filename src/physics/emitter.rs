@@ -0,0 +1,92 @@
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{Drain, Emitter};
+}
+
+/// Constantly fills a circle with water, moving at a velocity.
+/// Cells are despawned whenever the grids are rebuilt, so this goes on its own entity, and is found in the grids by its transform each step.
+/// It shouldn't have a parent, as its Transform is used as its GlobalTransform, which isn't updated until the end of the frame it was spawned in.
+#[derive(Component)]
+#[require(Transform)]
+pub struct Emitter {
+    pub radius: f32,
+    /// The velocity the water leaves at, in world units per second.
+    pub velocity: Vec2,
+}
+
+/// Removes all water that reaches a circle.
+/// Like emitters, this goes on its own entity with a transform.
+#[derive(Component)]
+#[require(Transform)]
+pub struct Drain {
+    pub radius: f32,
+}
+
+#[system(FluidUpdate::Fluid::Forces)]
+fn emit(
+    emitters: Query<(&Emitter, &Transform)>,
+    grids: Query<&Grid>,
+    mut fluid_cells: ResMut<FluidCells>,
+) {
+    emitters.iter().for_each(|(emitter, transform)| {
+        let translation = transform.translation.xy();
+
+        grids.iter().for_each(|grid| {
+            let first_cell = grid.cells_range().start;
+
+            grid.region()
+                .cells_in_circle(translation, emitter.radius)
+                .for_each(|index| {
+                    let index = first_cell + index;
+                    if fluid_cells.solid[index] {
+                        return;
+                    }
+
+                    let distance =
+                        fluid_cells.translation[index].distance(translation) - emitter.radius;
+                    fluid_cells.level_set[index] = fluid_cells.level_set[index].min(distance);
+                    fluid_cells.velocity[index] = emitter.velocity;
+                });
+        });
+    });
+}
+
+#[system(FluidUpdate::Fluid::Forces)]
+fn drain(
+    drains: Query<(&Drain, &Transform)>,
+    particles: Query<(Entity, &WaterParticle)>,
+    grids: Query<&Grid>,
+    mut fluid_cells: ResMut<FluidCells>,
+    mut commands: Commands,
+) {
+    drains.iter().for_each(|(drain, transform)| {
+        let translation = transform.translation.xy();
+
+        // Particles would carry the water straight back in, so they are taken out too.
+        particles
+            .iter()
+            .filter(|(_, particle)| particle.translation.distance(translation) < drain.radius)
+            .for_each(|(entity, _)| Pooled::pool(commands.entity(entity)));
+
+        grids.iter().for_each(|grid| {
+            let first_cell = grid.cells_range().start;
+
+            grid.region()
+                .cells_in_circle(translation, drain.radius)
+                .for_each(|index| {
+                    let index = first_cell + index;
+                    if fluid_cells.solid[index] {
+                        return;
+                    }
+
+                    // The circle is made air, so the water around it flows in and is removed the next step.
+                    let distance =
+                        drain.radius - fluid_cells.translation[index].distance(translation);
+                    fluid_cells.level_set[index] = fluid_cells.level_set[index].max(distance);
+                    fluid_cells.particle_level_set[index] =
+                        fluid_cells.particle_level_set[index].max(distance);
+                });
+        });
+    });
+}
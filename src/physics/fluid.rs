@@ -1,112 +1,581 @@
 // Almost everything comes from https://shahriyarshahrabi.medium.com/gentle-introduction-to-fluid-simulation-for-programmers-and-technical-artists-7c0045c40bac
 
+use super::surface::FAR;
 use crate::prelude::*;
+use bevy::{
+    ecs::system::SystemParam,
+    math::VectorSpace,
+    tasks::{ComputeTaskPool, ParallelSliceMut},
+};
+
+mod conjugate_gradient;
 
 pub mod prelude {
-    pub use super::Fluid;
+    pub use super::{
+        FluidCells, FluidSampler, PressureSolveReport, PressureSolver, Push, PushFluid,
+    };
 }
 
-#[derive(Component, Default)]
-#[require(Velocity, Pressure)]
-pub struct Fluid;
+/// The fluid in every grid.
+/// Each field is stored in its own array, which is a lot faster to run through than an entity per cell.
+/// Grids are stored one after another, so a cell's index is the index of its grid's first cell, plus its index in the grid.
+#[init]
+#[derive(Resource, Default)]
+pub struct FluidCells {
+    /// The 4 nearest cells.
+    /// Ordered top, left, right, bottom.
+    pub(super) nearest_4: Vec<[Option<usize>; 4]>,
+    /// Whether each missing neighbour is open, letting fluid flow in and out.
+    /// Missing neighbours that aren't open are walls.
+    pub(super) open: Vec<[bool; 4]>,
+    pub(super) translation: Vec<Vec2>,
+    pub(super) solid: Vec<bool>,
+    /// The temperature that thermal vents hold their cell at.
+    pub(super) heat_source: Vec<Option<f32>>,
 
-#[system(Update)]
-fn debug(
-    cells: Query<(&Cell, &Velocity, &VelocityDivergence, &Pressure)>,
-    grids: Query<&RenderLayers>,
-    mut gizmos: Gizmos,
-) {
-    cells
-        .iter()
-        .for_each(|(cell, velocity, velocity_divergence, pressure)| {
-            let Ok(grid) = grids.get(cell.grid) else {
-                return;
-            };
+    pub(super) velocity: Vec<Vec2>,
+    pub(super) velocity_divergence: Vec<f32>,
+    pub(super) pressure: Vec<f32>,
+    /// The colour of the fluid.
+    /// Alpha is how strongly the fluid is tinted, so clear water has an alpha of 0.
+    pub(super) dye: Vec<LinearRgba>,
+    /// The dye before the most recent step, which is drawn interpolated with the dye.
+    pub(super) previous_dye: Vec<LinearRgba>,
+    /// In degrees celsius.
+    pub(super) temperature: Vec<f32>,
+    /// How fast the fluid is spinning, anticlockwise.
+    pub(super) vorticity: Vec<f32>,
+    /// The distance to the water's surface, in world units.
+    /// Negative in the water, and positive in the air.
+    pub(super) level_set: Vec<f32>,
+    /// The same as the level set, but for the water that water particles make up.
+    /// Rebuilt from the particles every step, rather than being advected.
+    pub(super) particle_level_set: Vec<f32>,
+    /// The density of the liquid that water particles gave the grid, relative to fresh water.
+    /// Cells without particles are fresh water.
+    pub(super) liquid_density: Vec<f32>,
+    /// The velocity that water particles gave the grid.
+    /// Whatever changes after this, from forces and the pressure solve, is given back to the particles.
+    pub(super) transferred_velocity: Vec<Vec2>,
 
-            if *grid != gizmos.config.render_layers {
-                return;
-            }
+    // Fields are calculated into these, and then swapped in, so that nothing reads values that have already been changed.
+    pub(super) velocity_update: Vec<Vec2>,
+    pub(super) dye_update: Vec<LinearRgba>,
+    pub(super) temperature_update: Vec<f32>,
+    pub(super) level_set_update: Vec<f32>,
+}
 
-            gizmos.rect_2d(
-                cell.translation,
-                Vec2::splat(Cell::SIZE * 0.9),
-                Srgba::new(velocity_divergence.0 * 0.25, pressure.0 * 0.1, 0., 1.),
-            );
+impl FluidCells {
+    /// Adds the cells for a grid onto the end, filled with water up to the water line.
+    /// Returns the index of the grid's first cell.
+    pub(super) fn push_grid(&mut self, region: &Region, water_line: f32) -> usize {
+        let first_cell = self.len();
+        let length = (region.size.x * region.size.y) as usize;
 
-            gizmos.arrow_2d(cell.translation, cell.translation + velocity.0, Srgba::BLUE);
+        (0..length).for_each(|index| {
+            self.nearest_4.push(
+                region
+                    .nearest_4(index)
+                    .map(|neighbour| neighbour.map(|neighbour| neighbour + first_cell)),
+            );
+            // Index is part of the grid, so this will not panic.
+            let translation = region.index_to_translation(index).unwrap();
+            self.translation.push(translation);
+            self.level_set.push(translation.y - water_line);
         });
+
+        let new_length = first_cell + length;
+        self.open.resize(new_length, [false; 4]);
+        self.solid.resize(new_length, false);
+        self.heat_source.resize(new_length, None);
+        self.velocity.resize(new_length, Vec2::ZERO);
+        self.velocity_divergence.resize(new_length, 0.);
+        self.pressure.resize(new_length, 0.);
+        self.dye.resize(new_length, LinearRgba::NONE);
+        self.previous_dye.resize(new_length, LinearRgba::NONE);
+        self.temperature.resize(new_length, AMBIENT_TEMPERATURE);
+        self.vorticity.resize(new_length, 0.);
+        self.particle_level_set.resize(new_length, FAR);
+        self.transferred_velocity.resize(new_length, Vec2::ZERO);
+        self.liquid_density.resize(new_length, 1.);
+        self.velocity_update.resize(new_length, Vec2::ZERO);
+        self.dye_update.resize(new_length, LinearRgba::NONE);
+        self.temperature_update
+            .resize(new_length, AMBIENT_TEMPERATURE);
+        self.level_set_update.resize(new_length, 0.);
+
+        first_cell
+    }
+
+    /// Copies a cell from another FluidCells, which is used when grids are rebuilt.
+    /// Only the state of the cell is copied, the cell still has its own translation and neighbours.
+    pub(super) fn copy_cell(&mut self, index: usize, from: &FluidCells, from_index: usize) {
+        self.solid[index] = from.solid[from_index];
+        self.heat_source[index] = from.heat_source[from_index];
+        self.velocity[index] = from.velocity[from_index];
+        self.pressure[index] = from.pressure[from_index];
+        self.dye[index] = from.dye[from_index];
+        self.previous_dye[index] = from.previous_dye[from_index];
+        self.temperature[index] = from.temperature[from_index];
+        self.level_set[index] = from.level_set[from_index];
+        self.particle_level_set[index] = from.particle_level_set[from_index];
+        self.transferred_velocity[index] = from.transferred_velocity[from_index];
+        self.liquid_density[index] = from.liquid_density[from_index];
+    }
+
+    /// The number of cells, across all grids.
+    pub fn len(&self) -> usize {
+        self.translation.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.translation.is_empty()
+    }
+
+    pub fn nearest_4(&self) -> &[[Option<usize>; 4]] {
+        &self.nearest_4
+    }
+
+    pub fn open(&self) -> &[[bool; 4]] {
+        &self.open
+    }
+
+    /// The world space translation of each cell's centre.
+    pub fn translation(&self) -> &[Vec2] {
+        &self.translation
+    }
+
+    pub fn solid(&self) -> &[bool] {
+        &self.solid
+    }
+
+    pub fn velocity(&self) -> &[Vec2] {
+        &self.velocity
+    }
+
+    pub fn velocity_divergence(&self) -> &[f32] {
+        &self.velocity_divergence
+    }
+
+    pub fn pressure(&self) -> &[f32] {
+        &self.pressure
+    }
+
+    pub fn dye(&self) -> &[LinearRgba] {
+        &self.dye
+    }
+
+    pub fn temperature(&self) -> &[f32] {
+        &self.temperature
+    }
+
+    pub fn level_set(&self) -> &[f32] {
+        &self.level_set
+    }
+
+    pub fn particle_level_set(&self) -> &[f32] {
+        &self.particle_level_set
+    }
+
+    /// Whether the cell is water, rather than air or a solid.
+    /// Cells are water if either the level set or the water particles say so.
+    pub fn liquid(&self, index: usize) -> bool {
+        !self.solid[index] && (self.level_set[index] < 0. || self.particle_level_set[index] < 0.)
+    }
+
+    /// Bilinearly interpolates a field at the translation, using the cells of the grid.
+    /// Solid cells are skipped, and the rest are weighted to make up for them.
+    /// Returns zero if all of the cells are solid.
+    pub fn sample<T: VectorSpace>(&self, grid: &Grid, field: &[T], translation: Vec2) -> T {
+        let first_cell = grid.cells_range().start;
+
+        let (sum, total_weight) = grid
+            .region()
+            .bilinear(translation)
+            .into_iter()
+            .flatten()
+            .fold((T::ZERO, 0.), |(sum, total_weight), (index, weight)| {
+                let index = first_cell + index;
+                if self.solid[index] {
+                    (sum, total_weight)
+                } else {
+                    (sum + field[index] * weight, total_weight + weight)
+                }
+            });
+
+        if total_weight == 0. {
+            T::ZERO
+        } else {
+            sum * (1. / total_weight)
+        }
+    }
+
+    /// The same as sample, but if the translation has left the grid, then whichever grid it is now in is sampled instead.
+    /// If it isn't in any grid, then it is wrapped across the grid's periodic edges.
+    /// This lets fluid be advected across the seams between grids, and around periodic edges.
+    pub fn sample_across<T: VectorSpace>(
+        &self,
+        grids: &[&Grid],
+        grid: &Grid,
+        field: &[T],
+        translation: Vec2,
+    ) -> T {
+        let grid = if grid.region().contains(translation) {
+            grid
+        } else {
+            grids
+                .iter()
+                .find(|grid| grid.region().contains(translation))
+                .copied()
+                .unwrap_or(grid)
+        };
+
+        self.sample(grid, field, grid.wrap(translation))
+    }
 }
 
-#[derive(Component, Default)]
-#[require(VelocityDivergence)]
-pub struct Velocity(Vec2);
+/// Reads the fluid anywhere in the world, without having to know which grid it is in.
+#[derive(SystemParam)]
+pub struct FluidSampler<'w, 's> {
+    grids: Query<'w, 's, &'static Grid>,
+    fluid_cells: Res<'w, FluidCells>,
+}
+
+impl FluidSampler<'_, '_> {
+    /// Bilinearly interpolates any field of FluidCells at the translation.
+    /// Returns None if the translation isn't inside a grid.
+    pub fn sample<T: VectorSpace>(
+        &self,
+        field: impl FnOnce(&FluidCells) -> &[T],
+        translation: Vec2,
+    ) -> Option<T> {
+        let grid = self
+            .grids
+            .iter()
+            .find(|grid| grid.region().contains(translation))?;
+
+        Some(
+            self.fluid_cells
+                .sample(grid, field(&self.fluid_cells), translation),
+        )
+    }
 
-#[derive(Component, Default)]
-pub struct VelocityDivergence(f32);
+    /// How fast the fluid is moving, in world units per second.
+    pub fn velocity(&self, translation: Vec2) -> Option<Vec2> {
+        self.sample(FluidCells::velocity, translation)
+    }
 
-#[system(Update::Fluid::VelocityDivergence)]
-fn velocity_divergence(
-    mut velocity_divergence: Query<(&Cell, &mut VelocityDivergence)>,
-    velocity: Query<&Velocity>,
+    pub fn pressure(&self, translation: Vec2) -> Option<f32> {
+        self.sample(FluidCells::pressure, translation)
+    }
+
+    /// In degrees celsius.
+    pub fn temperature(&self, translation: Vec2) -> Option<f32> {
+        self.sample(FluidCells::temperature, translation)
+    }
+
+    /// The distance to the water's surface, which is negative in the water.
+    /// This includes the water made up of water particles.
+    pub fn level_set(&self, translation: Vec2) -> Option<f32> {
+        let level_set = self.sample(FluidCells::level_set, translation)?;
+        let particle_level_set = self.sample(FluidCells::particle_level_set, translation)?;
+        Some(level_set.min(particle_level_set))
+    }
+
+    /// Whether the translation is inside a solid cell.
+    /// Returns None if the translation isn't inside a grid.
+    pub fn solid(&self, translation: Vec2) -> Option<bool> {
+        self.grids
+            .iter()
+            .find_map(|grid| grid.index(translation))
+            .map(|index| self.fluid_cells.solid[index])
+    }
+}
+
+/// Pushes the fluid in a circle, strongest at the centre and fading out to nothing at the radius.
+/// This works across grids, so it can push fluid on multiple monitors at once.
+#[init]
+#[derive(Event)]
+pub struct PushFluid {
+    pub translation: Vec2,
+    pub radius: f32,
+    pub push: Push,
+}
+
+/// How fluid is pushed.
+#[derive(Clone, Copy)]
+pub enum Push {
+    /// Instantly changes the velocity by this much.
+    Impulse(Vec2),
+    /// Accelerates the fluid by this much per second, for the frame it was sent.
+    /// Send it every frame for a constant force.
+    Force(Vec2),
+}
+
+#[system(Update)]
+fn push_fluid(
+    mut push_fluid: EventReader<PushFluid>,
+    grids: Query<&Grid>,
+    mut fluid_cells: ResMut<FluidCells>,
+    time: Res<Time>,
 ) {
-    velocity_divergence
-        .par_iter_mut()
-        .for_each(|(cell, mut velocity_divergence)| {
-            // This mess gets the nearest 4 velocities.
-            // If it can't get one (Edge of grid.), then it instead gives a default value.
-            let mut velocities = cell.nearest_4.iter().map(|entity| {
-                entity
-                    .and_then(|entity| velocity.get(entity).ok().map(|velocity| velocity.0))
-                    .unwrap_or(Vec2::ZERO)
-            });
-            let velocities: [Vec2; 4] = std::array::from_fn(|_| velocities.next().unwrap());
+    let time_delta_seconds = time.delta_secs();
+
+    push_fluid.read().for_each(|push_fluid| {
+        let velocity_change = match push_fluid.push {
+            Push::Impulse(impulse) => impulse,
+            Push::Force(force) => force * time_delta_seconds,
+        };
+
+        grids.iter().for_each(|grid| {
+            grid.region()
+                .cells_in_circle(push_fluid.translation, push_fluid.radius)
+                .for_each(|index| {
+                    let index = grid.cells_range().start + index;
+                    if fluid_cells.solid[index] {
+                        return;
+                    }
+
+                    let distance = fluid_cells.translation[index].distance(push_fluid.translation);
+                    let falloff = 1. - distance / push_fluid.radius;
+
+                    fluid_cells.velocity[index] += velocity_change * falloff;
+                });
+        });
+    });
+}
 
-            velocity_divergence.0 = divergence(velocities);
+/// Calls the function on every value, along with its index, spread across threads.
+pub(super) fn par_for_each_mut<T: Send>(
+    mut values: &mut [T],
+    function: impl Fn(usize, &mut T) + Send + Sync,
+) {
+    const CHUNK_SIZE: usize = 1024;
+
+    values.par_chunk_map_mut(ComputeTaskPool::get(), CHUNK_SIZE, |chunk_index, chunk| {
+        chunk.iter_mut().enumerate().for_each(|(index, value)| {
+            function(chunk_index * CHUNK_SIZE + index, value);
         });
+    });
+}
+
+#[system(Update)]
+fn debug(grids: Query<&Grid>, fluid_cells: Res<FluidCells>, mut gizmos: Gizmos) {
+    // Gizmos are on layer 0, which every window can see, and every grid has its own place in the world, so we can draw all of them.
+    grids.iter().for_each(|grid| {
+        grid.cells_range().for_each(|index| {
+            let translation = fluid_cells.translation[index];
+            gizmos.arrow_2d(
+                translation,
+                translation + fluid_cells.velocity[index],
+                Srgba::BLUE,
+            );
+        });
+    });
+}
+
+/// Gets the nearest 4 values of a field.
+/// If a neighbour is a wall (A solid, or a wall edge.), then it instead gives the wall value, and if it is an open edge, it gives the open value.
+fn nearest_4_or<T: Copy>(
+    fluid_cells: &FluidCells,
+    field: &[T],
+    index: usize,
+    wall: T,
+    open: T,
+) -> [T; 4] {
+    std::array::from_fn(|direction| match fluid_cells.nearest_4[index][direction] {
+        Some(neighbour) if !fluid_cells.solid[neighbour] => field[neighbour],
+        None if fluid_cells.open[index][direction] => open,
+        _ => wall,
+    })
+}
+
+#[system(FluidUpdate::Fluid::VelocityDivergence)]
+fn velocity_divergence(mut fluid_cells: ResMut<FluidCells>) {
+    let mut velocity_divergence = std::mem::take(&mut fluid_cells.velocity_divergence);
+
+    par_for_each_mut(&mut velocity_divergence, |index, velocity_divergence| {
+        // Walls don't move, so treating them as still stops fluid flowing through them.
+        // Open edges carry on moving the same way, so fluid flows straight through them.
+        let velocities = nearest_4_or(
+            &fluid_cells,
+            &fluid_cells.velocity,
+            index,
+            Vec2::ZERO,
+            fluid_cells.velocity[index],
+        );
+        *velocity_divergence = divergence(velocities);
+    });
+
+    fluid_cells.velocity_divergence = velocity_divergence;
 }
 
-#[derive(Component, Default)]
-#[require(PressureUpdate)]
-struct Pressure(f32);
+/// Settings for the pressure solve.
+#[init]
+#[derive(Resource)]
+pub struct PressureSolver {
+    /// The solve stops once no cell's pressure equation is off by more than this.
+    /// Set with `--pressure-tolerance` on the command line.
+    pub tolerance: f32,
+    /// The solve stops after this many iterations, even if it isn't within tolerance.
+    /// Set with `--pressure-iterations` on the command line.
+    pub max_iterations: usize,
+}
+
+impl Default for PressureSolver {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.001,
+            max_iterations: 200,
+        }
+    }
+}
 
-#[derive(Component, Default)]
-struct PressureUpdate(f32);
+/// How the most recent pressure solve went.
+/// The water tool shows this.
+#[init]
+#[derive(Resource, Default, Debug)]
+pub struct PressureSolveReport {
+    pub iterations: usize,
+    pub residual: f32,
+}
 
-#[system(Update::Fluid::Solve)]
+#[system(FluidUpdate::Fluid::Solve)]
 fn solve(
-    mut calculate_and_update: ParamSet<(
-        (
-            Query<(&Cell, &mut PressureUpdate, &VelocityDivergence, &Pressure)>,
-            Query<&Pressure, Without<Solid>>,
-        ),
-        Query<(&mut Pressure, &PressureUpdate)>,
-    )>,
+    mut fluid_cells: ResMut<FluidCells>,
+    pressure_solver: Res<PressureSolver>,
+    mut report: ResMut<PressureSolveReport>,
 ) {
-    (0..30).for_each(|_| {
-        let (mut pressure_update, pressure) = calculate_and_update.p0();
-
-        pressure_update.par_iter_mut().for_each(
-            |(cell, mut pressure_update, velocity_divergence, center_pressure)| {
-                let mut sum_of_neighbours = 0.;
-                cell.nearest_4.iter().for_each(|entity| {
-                    let pressure = entity
-                        .and_then(|entity| pressure.get(entity).ok())
-                        .map(|pressure| pressure.0)
-                        .unwrap_or(center_pressure.0);
-                    sum_of_neighbours += pressure;
-                });
+    let kinds: Vec<conjugate_gradient::Kind> = (0..fluid_cells.len())
+        .map(|index| {
+            if fluid_cells.solid[index] {
+                conjugate_gradient::Kind::Solid
+            } else if fluid_cells.liquid(index) {
+                conjugate_gradient::Kind::Liquid
+            } else {
+                conjugate_gradient::Kind::Air
+            }
+        })
+        .collect();
 
-                pressure_update.0 = (sum_of_neighbours - velocity_divergence.0) / 4.;
-            },
+    let FluidCells {
+        nearest_4,
+        open,
+        velocity_divergence,
+        pressure,
+        ..
+    } = &mut *fluid_cells;
+
+    let conjugate_gradient::Report {
+        iterations,
+        residual,
+    } = conjugate_gradient::solve(
+        nearest_4,
+        open,
+        &kinds,
+        velocity_divergence,
+        pressure,
+        pressure_solver.tolerance,
+        pressure_solver.max_iterations,
+    );
+    report.iterations = iterations;
+    report.residual = residual;
+}
+
+#[system(FluidUpdate::Fluid::Project)]
+fn project(mut fluid_cells: ResMut<FluidCells>) {
+    let mut velocity = std::mem::take(&mut fluid_cells.velocity);
+
+    par_for_each_mut(&mut velocity, |index, velocity| {
+        // Air has no pressure, so only water is pushed around by it.
+        if !fluid_cells.liquid(index) {
+            return;
+        }
+
+        // Air has 0 pressure, which the solve leaves it at.
+        // Walls and open edges are treated the same way as in solve, so the gradient matches the pressure we solved for.
+        let pressures = nearest_4_or(
+            &fluid_cells,
+            &fluid_cells.pressure,
+            index,
+            fluid_cells.pressure[index],
+            0.,
         );
 
-        calculate_and_update
-            .p1()
-            .par_iter_mut()
-            .for_each(|(mut pressure, pressure_update)| {
-                pressure.0 = pressure_update.0;
+        // Both divergence and gradient skip halving their central differences, so we divide by 4 instead of 1.
+        *velocity -= gradient(pressures) / 4.;
+    });
+
+    fluid_cells.velocity = velocity;
+}
+
+/// Moves the velocity along with itself.
+/// We trace backwards from each cell to find where its fluid came from, and take the velocity that was there.
+#[system(FluidUpdate::Fluid::Advect)]
+fn advect(mut fluid_cells: ResMut<FluidCells>, grids: Query<&Grid>, fluid_time: Res<FluidTime>) {
+    let time_delta_seconds = fluid_time.delta_secs();
+
+    let mut advected_velocity = std::mem::take(&mut fluid_cells.velocity_update);
+    let all_grids: Vec<&Grid> = grids.iter().collect();
+
+    grids.iter().for_each(|grid| {
+        let range = grid.cells_range();
+        let first_cell = range.start;
+
+        par_for_each_mut(&mut advected_velocity[range], |index, advected_velocity| {
+            let index = first_cell + index;
+            if fluid_cells.solid[index] {
+                *advected_velocity = Vec2::ZERO;
+                return;
+            }
+
+            let previous_translation =
+                fluid_cells.translation[index] - fluid_cells.velocity[index] * time_delta_seconds;
+
+            *advected_velocity = fluid_cells.sample_across(
+                &all_grids,
+                grid,
+                &fluid_cells.velocity,
+                previous_translation,
+            );
+        });
+    });
+
+    fluid_cells.velocity_update = std::mem::replace(&mut fluid_cells.velocity, advected_velocity);
+}
+
+/// Solids and walls can't be flowed into, so any velocity pointing into one is removed.
+/// Fluid is still allowed to flow away from them.
+#[system(FluidUpdate::Fluid::Boundaries)]
+fn no_through_flow(mut fluid_cells: ResMut<FluidCells>) {
+    let mut velocity = std::mem::take(&mut fluid_cells.velocity);
+
+    par_for_each_mut(&mut velocity, |index, velocity| {
+        if fluid_cells.solid[index] {
+            return;
+        }
+
+        let [top, left, right, bottom] =
+            std::array::from_fn(|direction| match fluid_cells.nearest_4[index][direction] {
+                Some(neighbour) => fluid_cells.solid[neighbour],
+                None => !fluid_cells.open[index][direction],
             });
+
+        if top {
+            velocity.y = velocity.y.min(0.);
+        }
+        if left {
+            velocity.x = velocity.x.max(0.);
+        }
+        if right {
+            velocity.x = velocity.x.min(0.);
+        }
+        if bottom {
+            velocity.y = velocity.y.max(0.);
+        }
     });
+
+    fluid_cells.velocity = velocity;
 }
 
 /// Calculates the divergence.
@@ -115,19 +584,331 @@ fn divergence(nearest_4: [Vec2; 4]) -> f32 {
     (nearest_4[2].x - nearest_4[1].x) + (nearest_4[0].y - nearest_4[3].y)
 }
 
+/// Calculates the curl.
+/// Nearest 4 is ordered top, left, right, bottom.
+fn curl(nearest_4: [Vec2; 4]) -> f32 {
+    (nearest_4[2].y - nearest_4[1].y) - (nearest_4[0].x - nearest_4[3].x)
+}
+
 /// Calculates the gradient.
 /// Nearest 4 is ordered top, left, right, bottom.
 fn gradient(nearest_4: [f32; 4]) -> Vec2 {
     Vec2::new(nearest_4[2] - nearest_4[1], nearest_4[0] - nearest_4[3])
 }
 
-#[system(Update::Fluid::Forces)]
-fn gravity(mut velocity: Query<&mut Velocity>, time: Res<Time>) {
-    let time_delta_seconds = time.delta_secs();
-    velocity.par_iter_mut().for_each(|mut velocity| {
-        velocity.0.y -= 5. * time_delta_seconds;
+/// The temperature that fluid is at when nothing is heating it.
+pub const AMBIENT_TEMPERATURE: f32 = 20.;
+/// How much the fluid's density drops for each degree above ambient.
+const THERMAL_EXPANSION: f32 = 0.01;
+/// How quickly heat spreads to neighbouring cells, per second.
+const THERMAL_DIFFUSION: f32 = 1.;
+/// How quickly fluid returns to ambient, per second.
+const COOLING: f32 = 0.05;
+/// How fast the fluid falls, in world units per second squared.
+/// This is a lot weaker than the gravity on rigid bodies, as the fluid's forces and solver settings are tuned for it.
+pub(super) const FLUID_GRAVITY: Vec2 = Vec2::new(0., -5.);
+
+/// Moves the temperature along with the velocity, the same way the velocity is advected.
+#[system(FluidUpdate::Fluid::Advect)]
+fn advect_temperature(
+    mut fluid_cells: ResMut<FluidCells>,
+    grids: Query<&Grid>,
+    fluid_time: Res<FluidTime>,
+) {
+    let time_delta_seconds = fluid_time.delta_secs();
+
+    let mut advected_temperature = std::mem::take(&mut fluid_cells.temperature_update);
+    let all_grids: Vec<&Grid> = grids.iter().collect();
+
+    grids.iter().for_each(|grid| {
+        let range = grid.cells_range();
+        let first_cell = range.start;
+
+        par_for_each_mut(
+            &mut advected_temperature[range],
+            |index, advected_temperature| {
+                let index = first_cell + index;
+                if fluid_cells.solid[index] {
+                    *advected_temperature = AMBIENT_TEMPERATURE;
+                    return;
+                }
+
+                let previous_translation = fluid_cells.translation[index]
+                    - fluid_cells.velocity[index] * time_delta_seconds;
+
+                *advected_temperature = fluid_cells.sample_across(
+                    &all_grids,
+                    grid,
+                    &fluid_cells.temperature,
+                    previous_translation,
+                );
+            },
+        );
+    });
+
+    fluid_cells.temperature_update =
+        std::mem::replace(&mut fluid_cells.temperature, advected_temperature);
+}
+
+/// Spreads heat between neighbouring cells, slowly cools everything back to ambient, and keeps thermal vents hot.
+#[system(FluidUpdate::Fluid::Diffuse)]
+fn diffuse_temperature(mut fluid_cells: ResMut<FluidCells>, fluid_time: Res<FluidTime>) {
+    let time_delta_seconds = fluid_time.delta_secs();
+    // Any more than a quarter and the cell would give away more heat than it has, which explodes.
+    let diffusion = (THERMAL_DIFFUSION * time_delta_seconds).min(0.25);
+    let cooling = (COOLING * time_delta_seconds).min(1.);
+
+    let mut diffused_temperature = std::mem::take(&mut fluid_cells.temperature_update);
+
+    par_for_each_mut(&mut diffused_temperature, |index, diffused_temperature| {
+        if let Some(heat_source) = fluid_cells.heat_source[index] {
+            *diffused_temperature = heat_source;
+            return;
+        }
+
+        let center_temperature = fluid_cells.temperature[index];
+
+        // Walls and solids are insulators, so we pretend they are the same temperature as the center.
+        // Open edges lead to more fluid, which is at ambient.
+        let sum_of_differences: f32 = nearest_4_or(
+            &fluid_cells,
+            &fluid_cells.temperature,
+            index,
+            center_temperature,
+            AMBIENT_TEMPERATURE,
+        )
+        .into_iter()
+        .map(|temperature| temperature - center_temperature)
+        .sum();
+
+        let diffused = center_temperature + sum_of_differences * diffusion;
+        *diffused_temperature = diffused + (AMBIENT_TEMPERATURE - diffused) * cooling;
+    });
+
+    fluid_cells.temperature_update =
+        std::mem::replace(&mut fluid_cells.temperature, diffused_temperature);
+}
+
+#[system(FluidUpdate::Fluid::Forces)]
+fn gravity(mut fluid_cells: ResMut<FluidCells>, fluid_time: Res<FluidTime>) {
+    let time_delta_seconds = fluid_time.delta_secs();
+
+    let FluidCells {
+        solid,
+        velocity,
+        temperature,
+        liquid_density,
+        ..
+    } = &mut *fluid_cells;
+
+    par_for_each_mut(velocity, |index, velocity| {
+        if solid[index] {
+            return;
+        }
+
+        // Warm fluid is less dense, so gravity pulls on it less. Hot enough fluid will rise instead.
+        // Heavier liquids are pulled on more, so they sink below lighter ones.
+        let density =
+            liquid_density[index] - THERMAL_EXPANSION * (temperature[index] - AMBIENT_TEMPERATURE);
+        *velocity += FLUID_GRAVITY * density * time_delta_seconds;
+
+        let velocity_delta = velocity.abs() * *velocity * 0.005 * time_delta_seconds;
+        *velocity -= velocity_delta;
+    });
+}
+
+/// How much small swirls are strengthened, to make up for the solver smoothing them out.
+/// A strength of 0 turns it off.
+#[init]
+#[derive(Resource)]
+pub struct VorticityConfinement {
+    pub strength: f32,
+}
+
+impl Default for VorticityConfinement {
+    fn default() -> Self {
+        Self { strength: 0.5 }
+    }
+}
+
+#[system(FluidUpdate::Fluid::Vorticity)]
+fn vorticity(
+    mut fluid_cells: ResMut<FluidCells>,
+    grids: Query<&Grid>,
+    vorticity_confinement: Res<VorticityConfinement>,
+) {
+    if vorticity_confinement.strength == 0. {
+        return;
+    }
+
+    let mut vorticity = std::mem::take(&mut fluid_cells.vorticity);
+
+    grids.iter().for_each(|grid| {
+        let range = grid.cells_range();
+        let first_cell = range.start;
+        let cell_size = grid.cell_size();
+
+        par_for_each_mut(&mut vorticity[range], |index, vorticity| {
+            let index = first_cell + index;
+
+            // Same as in velocity_divergence.
+            let velocities = nearest_4_or(
+                &fluid_cells,
+                &fluid_cells.velocity,
+                index,
+                Vec2::ZERO,
+                fluid_cells.velocity[index],
+            );
+            *vorticity = curl(velocities) / (2. * cell_size);
+        });
+    });
+
+    fluid_cells.vorticity = vorticity;
+}
+
+/// Pushes fluid around the centre of each swirl, so that it keeps spinning.
+#[system(FluidUpdate::Fluid::Forces)]
+fn vorticity_confinement(
+    mut fluid_cells: ResMut<FluidCells>,
+    grids: Query<&Grid>,
+    vorticity_confinement: Res<VorticityConfinement>,
+    fluid_time: Res<FluidTime>,
+) {
+    if vorticity_confinement.strength == 0. {
+        return;
+    }
+
+    let time_delta_seconds = fluid_time.delta_secs();
+
+    let mut velocity = std::mem::take(&mut fluid_cells.velocity);
+
+    grids.iter().for_each(|grid| {
+        let range = grid.cells_range();
+        let first_cell = range.start;
+        let cell_size = grid.cell_size();
+
+        par_for_each_mut(&mut velocity[range], |index, velocity| {
+            let index = first_cell + index;
+
+            if fluid_cells.solid[index] {
+                return;
+            }
 
-        let velocity_delta = velocity.0.abs() * velocity.0 * 0.005 * time_delta_seconds;
-        velocity.0 -= velocity_delta;
+            let center_vorticity = fluid_cells.vorticity[index];
+            let magnitudes = fluid_cells.nearest_4[index].map(|neighbour| {
+                neighbour
+                    .map(|neighbour| fluid_cells.vorticity[neighbour])
+                    .unwrap_or(center_vorticity)
+                    .abs()
+            });
+
+            // Points towards the centre of the swirl.
+            let Some(normal) = gradient(magnitudes).try_normalize() else {
+                return;
+            };
+
+            // The cross product of the normal and the vorticity, which points along the swirl.
+            let force = Vec2::new(normal.y, -normal.x)
+                * center_vorticity
+                * vorticity_confinement.strength
+                * cell_size;
+
+            *velocity += force * time_delta_seconds;
+        });
     });
+
+    fluid_cells.velocity = velocity;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    /// A 4 by 4 grid of 10 unit cells, with the bottom left cell's centre at the world origin.
+    fn world() -> World {
+        let mut fluid_cells = FluidCells::default();
+        let grid = Grid::test(Vec2::ZERO, UVec2::splat(4), 10., &mut fluid_cells);
+
+        let mut world = World::new();
+        world.spawn(grid);
+        world.insert_resource(fluid_cells);
+        world
+    }
+
+    fn index(world: &World, translation: Vec2) -> usize {
+        world
+            .resource::<FluidCells>()
+            .translation
+            .iter()
+            .position(|cell_translation| *cell_translation == translation)
+            .unwrap()
+    }
+
+    #[test]
+    fn sampler_interpolates_between_cells() {
+        let mut world = world();
+        let solid = index(&world, Vec2::new(30., 30.));
+        let mut fluid_cells = world.resource_mut::<FluidCells>();
+        // Bilinear interpolation is exact for fields that change linearly.
+        (0..fluid_cells.len()).for_each(|index| {
+            let translation = fluid_cells.translation[index];
+            fluid_cells.velocity[index] = Vec2::new(translation.x, -2. * translation.y);
+            fluid_cells.temperature[index] = translation.x + translation.y;
+        });
+        fluid_cells.solid[solid] = true;
+
+        let (velocity, temperature, solid, inside_solid, outside) = world
+            .run_system_once(|fluid_sampler: FluidSampler| {
+                let translation = Vec2::new(12., 17.);
+                (
+                    fluid_sampler.velocity(translation),
+                    fluid_sampler.temperature(translation),
+                    fluid_sampler.solid(translation),
+                    fluid_sampler.solid(Vec2::new(31., 29.)),
+                    fluid_sampler.velocity(Vec2::new(-100., 0.)),
+                )
+            })
+            .unwrap();
+
+        assert!(
+            velocity.unwrap().distance(Vec2::new(12., -34.)) < 1e-4,
+            "{velocity:?}"
+        );
+        assert!((temperature.unwrap() - 29.).abs() < 1e-4, "{temperature:?}");
+        assert_eq!(solid, Some(false));
+        assert_eq!(inside_solid, Some(true));
+        assert_eq!(outside, None);
+    }
+
+    #[test]
+    fn impulse_fades_out_to_the_radius() {
+        let mut world = world();
+        let centre = Vec2::new(10., 10.);
+        let centre_index = index(&world, centre);
+        let beside = index(&world, Vec2::new(20., 10.));
+        let solid = index(&world, Vec2::new(10., 20.));
+        let far = index(&world, Vec2::new(30., 30.));
+        world.resource_mut::<FluidCells>().solid[solid] = true;
+
+        world.init_resource::<Time>();
+        world.init_resource::<Events<PushFluid>>();
+        world.send_event(PushFluid {
+            translation: centre,
+            radius: 15.,
+            push: Push::Impulse(Vec2::new(0., -30.)),
+        });
+        world.run_system_once(push_fluid).unwrap();
+
+        let velocity = &world.resource::<FluidCells>().velocity;
+        assert_eq!(velocity[centre_index], Vec2::new(0., -30.));
+        // A cell 2 thirds of the way to the centre.
+        assert!(
+            velocity[beside].distance(Vec2::new(0., -10.)) < 1e-4,
+            "{}",
+            velocity[beside]
+        );
+        assert_eq!(velocity[solid], Vec2::ZERO);
+        assert_eq!(velocity[far], Vec2::ZERO);
+    }
 }
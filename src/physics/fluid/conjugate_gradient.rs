@@ -0,0 +1,233 @@
+// Based on the pressure solve from Robert Bridson's "Fluid Simulation for Computer Graphics", using a simpler preconditioner.
+
+/// How well a solve went.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Report {
+    pub iterations: usize,
+    /// The largest amount that any cell's equation was still off by.
+    pub residual: f32,
+}
+
+/// What is in a cell, as far as the pressure solve cares.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A wall, which is treated as having the same pressure as the centre.
+    Solid,
+    /// Solved for.
+    Liquid,
+    /// Always has 0 pressure.
+    Air,
+}
+
+/// Solves for the pressure that cancels out the divergence, using the jacobi preconditioned conjugate gradient method.
+/// Nearest 4 is ordered top, left, right, bottom, and holds indices into the other slices.
+/// Only liquid cells are solved for. Solid neighbours, and missing neighbours that aren't open, are walls, so they are treated as having the same pressure as the centre.
+/// Air and open neighbours are treated as having 0 pressure.
+/// The pressure that is passed in is used as the first guess, so reusing last frame's pressure converges faster.
+pub fn solve(
+    nearest_4: &[[Option<usize>; 4]],
+    open: &[[bool; 4]],
+    kinds: &[Kind],
+    divergence: &[f32],
+    pressure: &mut [f32],
+    tolerance: f32,
+    max_iterations: usize,
+) -> Report {
+    let length = pressure.len();
+
+    // The diagonal of the matrix is the number of liquid, air, and open neighbours, and every liquid neighbour is -1.
+    // Air and open neighbours have 0 pressure, so they only show up in the diagonal.
+    let neighbours_of_kind = |index: usize, kind: Kind| {
+        nearest_4[index]
+            .into_iter()
+            .flatten()
+            .filter(move |neighbour| kinds[*neighbour] == kind)
+    };
+    let diagonal: Vec<f32> = (0..length)
+        .map(|index| {
+            if kinds[index] == Kind::Liquid {
+                (neighbours_of_kind(index, Kind::Liquid).count()
+                    + neighbours_of_kind(index, Kind::Air).count()
+                    + open[index].iter().filter(|open| **open).count()) as f32
+            } else {
+                0.
+            }
+        })
+        .collect();
+    let multiply = |vector: &[f32], result: &mut [f32]| {
+        result.iter_mut().enumerate().for_each(|(index, result)| {
+            *result = if diagonal[index] == 0. {
+                0.
+            } else {
+                diagonal[index] * vector[index]
+                    - neighbours_of_kind(index, Kind::Liquid)
+                        .map(|neighbour| vector[neighbour])
+                        .sum::<f32>()
+            };
+        });
+    };
+    let precondition = |residual: &[f32], result: &mut [f32]| {
+        result.iter_mut().enumerate().for_each(|(index, result)| {
+            *result = if diagonal[index] == 0. {
+                0.
+            } else {
+                residual[index] / diagonal[index]
+            };
+        });
+    };
+    let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let max = |vector: &[f32]| {
+        vector
+            .iter()
+            .fold(0., |max: f32, value| max.max(value.abs()))
+    };
+
+    // Cells that aren't solved for, like air, have 0 pressure.
+    pressure
+        .iter_mut()
+        .zip(&diagonal)
+        .filter(|(_, diagonal)| **diagonal == 0.)
+        .for_each(|(pressure, _)| *pressure = 0.);
+
+    let mut right_hand_side: Vec<f32> = (0..length)
+        .map(|index| {
+            if diagonal[index] == 0. {
+                0.
+            } else {
+                -divergence[index]
+            }
+        })
+        .collect();
+    // If a body of liquid only touches walls, then adding the same amount of pressure all through it changes nothing, so there are infinitely many answers.
+    // Removing its average divergence makes sure that at least one of them exists.
+    // Air and open neighbours pin the pressure down, so this isn't needed for bodies that touch any.
+    // Each body is solved independently of the others, so each one is checked on its own.
+    let mut visited = vec![false; length];
+    let mut bodies = Vec::new();
+    (0..length).for_each(|start| {
+        if diagonal[start] == 0. || visited[start] {
+            return;
+        }
+
+        let mut pinned = false;
+        let mut cells = vec![start];
+        visited[start] = true;
+        let mut next = 0;
+        while let Some(&index) = cells.get(next) {
+            next += 1;
+            pinned |= open[index].contains(&true)
+                || neighbours_of_kind(index, Kind::Air).next().is_some();
+            neighbours_of_kind(index, Kind::Liquid).for_each(|neighbour| {
+                if !visited[neighbour] {
+                    visited[neighbour] = true;
+                    cells.push(neighbour);
+                }
+            });
+        }
+
+        bodies.push((pinned, cells));
+    });
+    bodies
+        .iter()
+        .filter(|(pinned, _)| !pinned)
+        .for_each(|(_, cells)| {
+            let average = cells
+                .iter()
+                .map(|index| right_hand_side[*index])
+                .sum::<f32>()
+                / cells.len() as f32;
+            cells
+                .iter()
+                .for_each(|index| right_hand_side[*index] -= average);
+        });
+
+    let mut residual = vec![0.; length];
+    multiply(pressure, &mut residual);
+    residual
+        .iter_mut()
+        .zip(&right_hand_side)
+        .for_each(|(residual, right_hand_side)| *residual = right_hand_side - *residual);
+
+    let mut report = Report {
+        iterations: 0,
+        residual: max(&residual),
+    };
+    if report.residual <= tolerance {
+        return report;
+    }
+
+    let mut auxiliary = vec![0.; length];
+    precondition(&residual, &mut auxiliary);
+    let mut search = auxiliary.clone();
+    let mut sigma = dot(&residual, &auxiliary);
+
+    while report.iterations < max_iterations {
+        report.iterations += 1;
+
+        multiply(&search, &mut auxiliary);
+        let search_dot_auxiliary = dot(&search, &auxiliary);
+        if search_dot_auxiliary == 0. {
+            break;
+        }
+        let alpha = sigma / search_dot_auxiliary;
+
+        pressure
+            .iter_mut()
+            .zip(&search)
+            .for_each(|(pressure, search)| *pressure += alpha * search);
+        residual
+            .iter_mut()
+            .zip(&auxiliary)
+            .for_each(|(residual, auxiliary)| *residual -= alpha * auxiliary);
+
+        report.residual = max(&residual);
+        if report.residual <= tolerance {
+            break;
+        }
+
+        precondition(&residual, &mut auxiliary);
+        let new_sigma = dot(&residual, &auxiliary);
+        let beta = new_sigma / sigma;
+        sigma = new_sigma;
+
+        search
+            .iter_mut()
+            .zip(&auxiliary)
+            .for_each(|(search, auxiliary)| *search = auxiliary + beta * *search);
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A closed body of liquid, cells 0 and 1, next to a body with an open top, cell 2.
+    /// The closed body's divergence doesn't add up to 0, so it only has an answer once its average is removed.
+    #[test]
+    fn closed_body_next_to_open_body() {
+        let nearest_4 = [[None, None, Some(1), None], [None, Some(0), None, None], [
+            None, None, None, None,
+        ]];
+        let open = [[false; 4], [false; 4], [true, false, false, false]];
+        let kinds = [Kind::Liquid; 3];
+        let divergence = [1., 0., 1.];
+        let mut pressure = [0.; 3];
+
+        let report = solve(
+            &nearest_4,
+            &open,
+            &kinds,
+            &divergence,
+            &mut pressure,
+            1e-4,
+            100,
+        );
+
+        assert!(report.residual <= 1e-4, "{report:?}");
+        assert!(pressure.iter().all(|pressure| pressure.is_finite()));
+        // The open body is pinned, so its pressure is exact.
+        assert!((pressure[2] + 1.).abs() < 1e-4, "{pressure:?}");
+    }
+}
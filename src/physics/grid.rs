@@ -1,35 +1,116 @@
 use crate::prelude::*;
+use bevy::window::{WindowMoved, WindowResized, WindowScaleFactorChanged};
+use std::ops::Range;
 
 pub mod prelude {
-    pub use super::Grid;
+    pub use super::{Boundary, Grid, GridBoundaries, GridSettings, Region};
+}
+
+/// Where a grid raycast hit.
+#[derive(Clone, Copy, Debug)]
+pub struct GridRayHit {
+    /// The index of the cell that was hit.
+    pub index: usize,
+    /// The world space translation where the ray entered the cell.
+    pub translation: Vec2,
+    /// The side of the cell that the ray entered through.
+    /// This is zero if the ray started inside the cell.
+    pub normal: Vec2,
+}
+
+/// Settings used when creating grids.
+/// Changing these rebuilds every grid.
+#[init]
+#[derive(Resource)]
+pub struct GridSettings {
+    /// The width and height of each cell, in logical pixels.
+    /// Smaller cells look better, but are much slower.
+    /// Set with `--cell-size` on the command line.
+    pub cell_size: f32,
+    /// Used by every grid whose window doesn't have its own GridBoundaries.
+    pub boundaries: GridBoundaries,
+    /// How full of water new grids are, from 0 for empty to 1 for full.
+    /// When grids are rebuilt, this only applies to cells that weren't part of an old grid.
+    pub water_level: f32,
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self {
+            cell_size: 30.,
+            boundaries: default(),
+            water_level: 0.,
+        }
+    }
+}
+
+/// What happens to fluid at the edge of a grid.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Boundary {
+    /// Nothing can flow through it.
+    Wall,
+    /// Fluid flows freely in and out, as if there was more fluid beyond it.
+    Open,
+    /// Fluid that leaves comes back in through the opposite edge.
+    /// The opposite edge has to be periodic as well, otherwise both are walls.
+    Periodic,
+}
+
+/// The boundary of each edge of a grid.
+/// Edges that are joined to a grid on another monitor ignore this, and are walls wherever they aren't joined.
+/// Put this on a window to override GridSettings::boundaries for just its grid.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct GridBoundaries {
+    pub top: Boundary,
+    pub left: Boundary,
+    pub right: Boundary,
+    pub bottom: Boundary,
+}
+
+impl Default for GridBoundaries {
+    /// A floor and walls, with an open surface at the top.
+    fn default() -> Self {
+        Self {
+            top: Boundary::Open,
+            left: Boundary::Wall,
+            right: Boundary::Wall,
+            bottom: Boundary::Wall,
+        }
+    }
+}
+
+impl GridBoundaries {
+    /// Ordered top, left, right, bottom, same as nearest_4.
+    pub fn to_array(self) -> [Boundary; 4] {
+        [self.top, self.left, self.right, self.bottom]
+    }
 }
 
 /// The region that a grid takes up.
+#[derive(Clone, Copy)]
 pub struct Region {
     /// The bottom left corner world translation.
     origin: Vec2,
     /// Size in cells.
     pub size: UVec2,
+    /// The width and height of each cell.
+    cell_size: f32,
 }
 
 impl Region {
     /// Convert from a translation in world space to an index for a flattened array that represents the grid.
     /// Returns None if the translation is outside the grid.
     pub fn translation_to_index(&self, translation: Vec2) -> Option<usize> {
-        if translation.x < self.origin.x || translation.y < self.origin.y {
+        // The same bounds as contains, so that every translation inside the region has a cell.
+        if !self.contains(translation) {
             return None;
         }
 
-        // We remove origin so that (0,0) is the origin for our translation.
-        // We then divide by Cell::SIZE so that 1 unit is 1 cell.
-        // Rounding makes sure that the closest cell is found.
-        let grid_translation = ((translation - self.origin) / Cell::SIZE)
-            .round()
-            .as_uvec2();
-
-        if grid_translation.x >= self.size.x || grid_translation.y >= self.size.y {
-            return None;
-        }
+        // Cell centres land on whole numbers, so flooring half a cell further on finds the closest cell.
+        let grid_translation = (self.translation_to_grid_translation(translation) + 0.5)
+            .floor()
+            .as_uvec2()
+            .min(self.size - 1);
 
         let index = grid_translation.y * self.size.x + grid_translation.x;
         Some(index as usize)
@@ -38,26 +119,254 @@ impl Region {
     /// Converts the grid index to a translation.
     /// Returns None if the index is outside the grid.
     pub fn index_to_translation(&self, index: usize) -> Option<Vec2> {
-        if index >= self.size.x as usize * self.size.y as usize {
+        if index >= self.len() {
+            return None;
+        }
+
+        // Cell centres are at whole numbers in grid space, so the coordinates are the grid translation.
+        let coordinates = self.index_to_coordinates(index);
+        Some(self.grid_translation_to_translation(coordinates.as_vec2()))
+    }
+
+    /// Gets the indices of the 4 nearest cells.
+    /// Ordered top, left, right, bottom. Neighbours outside the grid are None.
+    pub fn nearest_4(&self, index: usize) -> [Option<usize>; 4] {
+        let coordinates = self.index_to_coordinates(index);
+
+        [IVec2::Y, IVec2::NEG_X, IVec2::X, IVec2::NEG_Y]
+            .map(|offset| self.coordinates_to_index(coordinates + offset))
+    }
+
+    /// Gets the indices of the 8 nearest cells, including the diagonals.
+    /// Ordered top left, top, top right, left, right, bottom left, bottom, bottom right. Neighbours outside the grid are None.
+    pub fn nearest_8(&self, index: usize) -> [Option<usize>; 8] {
+        let coordinates = self.index_to_coordinates(index);
+
+        [
+            IVec2::new(-1, 1),
+            IVec2::new(0, 1),
+            IVec2::new(1, 1),
+            IVec2::new(-1, 0),
+            IVec2::new(1, 0),
+            IVec2::new(-1, -1),
+            IVec2::new(0, -1),
+            IVec2::new(1, -1),
+        ]
+        .map(|offset| self.coordinates_to_index(coordinates + offset))
+    }
+
+    /// Converts an index to the column and row of the cell, starting from the bottom left.
+    /// This doesn't check that the index is inside the grid.
+    pub fn index_to_coordinates(&self, index: usize) -> IVec2 {
+        let width = self.size.x as usize;
+        IVec2::new((index % width) as i32, (index / width) as i32)
+    }
+
+    /// Converts the column and row of a cell to its index.
+    /// Returns None if the coordinates are outside the grid.
+    pub fn coordinates_to_index(&self, coordinates: IVec2) -> Option<usize> {
+        if coordinates.cmplt(IVec2::ZERO).any() || coordinates.as_uvec2().cmpge(self.size).any() {
             return None;
         }
 
-        let index = index as f32;
-        let width = self.size.x as f32;
+        Some(coordinates.y as usize * self.size.x as usize + coordinates.x as usize)
+    }
+
+    /// Convert from a translation in world space to a translation in grid space, where 1 unit is 1 cell.
+    /// Cell centres land on whole numbers. Unlike translation_to_index, this is neither rounded nor bounds checked.
+    pub fn translation_to_grid_translation(&self, translation: Vec2) -> Vec2 {
+        (translation - self.origin) / self.cell_size
+    }
+
+    /// Convert from a translation in grid space back to a translation in world space.
+    pub fn grid_translation_to_translation(&self, grid_translation: Vec2) -> Vec2 {
+        grid_translation * self.cell_size + self.origin
+    }
+
+    /// The width and height of each cell.
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// The number of cells.
+    pub fn len(&self) -> usize {
+        self.size.x as usize * self.size.y as usize
+    }
+
+    /// The world space rectangle that the cells cover.
+    /// Cell translations are their centres, so it goes half a cell past the outermost cell translations.
+    pub fn rect(&self) -> Rect {
+        let min = self.origin - self.cell_size / 2.;
+        Rect::from_corners(min, min + self.size.as_vec2() * self.cell_size)
+    }
+
+    /// Whether the translation is inside any of the cells.
+    /// This is the same as being inside rect, except that its top and right edges aren't included.
+    pub fn contains(&self, translation: Vec2) -> bool {
+        let grid_translation = self.translation_to_grid_translation(translation);
+        grid_translation.cmpge(Vec2::splat(-0.5)).all()
+            && grid_translation.cmplt(self.size.as_vec2() - 0.5).all()
+    }
+
+    /// Iterates the indices of every cell with its centre inside the world space rectangle.
+    pub fn cells_in_rect(&self, rect: Rect) -> impl Iterator<Item = usize> + use<> {
+        let min = self
+            .translation_to_grid_translation(rect.min)
+            .ceil()
+            .max(Vec2::ZERO)
+            .as_ivec2();
+        let max = self
+            .translation_to_grid_translation(rect.max)
+            .floor()
+            .min(self.size.as_vec2() - 1.)
+            .as_ivec2();
+        let width = self.size.x as usize;
+
+        // If the rectangle is outside the grid, or the grid has no cells, then max will be less than min, so nothing is iterated.
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| y as usize * width + x as usize))
+    }
+
+    /// Iterates the indices of every cell with its centre inside the world space circle.
+    pub fn cells_in_circle(
+        &self,
+        centre: Vec2,
+        radius: f32,
+    ) -> impl Iterator<Item = usize> + use<> {
+        let region = *self;
+
+        self.cells_in_rect(Rect::from_center_half_size(centre, Vec2::splat(radius)))
+            .filter(move |index| {
+                // The index came from the grid, so this will not panic.
+                region
+                    .index_to_translation(*index)
+                    .unwrap()
+                    .distance_squared(centre)
+                    <= radius * radius
+            })
+    }
+
+    /// Walks along a ray 1 cell at a time, and returns the first cell that hit returns true for.
+    /// The direction doesn't need to be normalised, and distances are in world space.
+    /// Returns None if nothing was hit before max distance, or before the ray left the grid.
+    pub fn raycast(
+        &self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+        mut hit: impl FnMut(usize) -> bool,
+    ) -> Option<GridRayHit> {
+        let direction = direction.try_normalize()?;
+
+        // Moves the grid translation by half a cell, so that cell edges land on whole numbers.
+        let start = self.translation_to_grid_translation(origin) + 0.5;
+        let mut coordinates = start.floor().as_ivec2();
+
+        // Zero is handled separately, as the edges on that axis are never reached.
+        let step = direction.to_array().map(|direction| {
+            if direction > 0. {
+                1
+            } else if direction < 0. {
+                -1
+            } else {
+                0
+            }
+        });
+        let step = IVec2::from(step);
+        // How far along the ray we have to go to cross 1 cell, in grid space.
+        let crossing_distance = (1. / direction).abs();
+        // How far along the ray the next edge is on each axis, in grid space.
+        let edge_distance = |start: f32, coordinate: i32, direction: f32| {
+            if direction > 0. {
+                (coordinate as f32 + 1. - start) / direction
+            } else if direction < 0. {
+                (start - coordinate as f32) / -direction
+            } else {
+                f32::INFINITY
+            }
+        };
+        let mut edge_distance = Vec2::new(
+            edge_distance(start.x, coordinates.x, direction.x),
+            edge_distance(start.y, coordinates.y, direction.y),
+        );
+
+        let max_distance = max_distance / self.cell_size;
+        let mut distance = 0.;
+        let mut normal = Vec2::ZERO;
+
+        while distance <= max_distance {
+            if let Some(index) = self.coordinates_to_index(coordinates) {
+                if hit(index) {
+                    return Some(GridRayHit {
+                        index,
+                        translation: origin + direction * distance * self.cell_size,
+                        normal,
+                    });
+                }
+            } else {
+                // Once the ray is outside the grid and moving away, it will never come back.
+                let moving_away = (coordinates.x < 0 && step.x <= 0)
+                    || (coordinates.y < 0 && step.y <= 0)
+                    || (coordinates.x >= self.size.x as i32 && step.x >= 0)
+                    || (coordinates.y >= self.size.y as i32 && step.y >= 0);
+                if moving_away {
+                    return None;
+                }
+            }
+
+            // Step across whichever edge is closest.
+            if edge_distance.x < edge_distance.y {
+                distance = edge_distance.x;
+                edge_distance.x += crossing_distance.x;
+                coordinates.x += step.x;
+                normal = Vec2::new(-step.x as f32, 0.);
+            } else {
+                distance = edge_distance.y;
+                edge_distance.y += crossing_distance.y;
+                coordinates.y += step.y;
+                normal = Vec2::new(0., -step.y as f32);
+            }
+        }
+
+        None
+    }
 
-        // We add origin at the end to put it back in world space.
-        let translation = Vec2::new(
-                // If you imagine every index in 1 line, then if you wrap the index back to 0 every time it reaches width, you will have x.
-                index % width,
-                // TODO: Explain how this works.
-                (index / width).floor()
-            )
-            // Converts 1 unit back into being Cell::SIZE.
-            * Cell::SIZE
-            // Converts the origin from [0,0] to the actual origin.
-            + self.origin;
+    /// Gets the 4 cells surrounding the translation, and how much each one should contribute when bilinearly interpolating.
+    /// Translations outside the grid are clamped to the edge.
+    /// Returns None if the grid has no cells.
+    pub fn bilinear(&self, translation: Vec2) -> Option<[(usize, f32); 4]> {
+        if self.len() == 0 {
+            return None;
+        }
 
-        Some(translation)
+        let max = (self.size - UVec2::ONE).as_vec2();
+        let grid_translation = self
+            .translation_to_grid_translation(translation)
+            .clamp(Vec2::ZERO, max);
+
+        // The bottom left cell, and how far along we are to the top right cell.
+        let bottom_left = grid_translation.floor();
+        let fraction = grid_translation - bottom_left;
+        let bottom_left = bottom_left.as_uvec2();
+        let top_right = (bottom_left + UVec2::ONE).min(self.size - UVec2::ONE);
+
+        let index = |x: u32, y: u32| (y * self.size.x + x) as usize;
+
+        Some([
+            (
+                index(bottom_left.x, bottom_left.y),
+                (1. - fraction.x) * (1. - fraction.y),
+            ),
+            (
+                index(top_right.x, bottom_left.y),
+                fraction.x * (1. - fraction.y),
+            ),
+            (
+                index(bottom_left.x, top_right.y),
+                (1. - fraction.x) * fraction.y,
+            ),
+            (index(top_right.x, top_right.y), fraction.x * fraction.y),
+        ])
     }
 }
 
@@ -66,9 +375,12 @@ impl Region {
 pub struct Grid {
     region: Region,
 
-    /// The grid cells are stored as components on an entity.
-    /// This means we don't have to deal with unsafety. Potentially at the cost of performance.
+    /// Each cell has an entity, so that other systems can attach components to it, like Solid.
+    /// The fluid itself is stored in FluidCells, because going through entities is too slow for the solver.
     cells: Box<[Entity]>,
+    /// Where this grid's cells start in FluidCells.
+    first_cell: usize,
+    boundaries: GridBoundaries,
 }
 
 impl Grid {
@@ -77,110 +389,415 @@ impl Grid {
         self.region.origin
     }
 
-    /// Gets the cell that the translation is inside.
+    /// The region that the grid takes up.
+    pub fn region(&self) -> &Region {
+        &self.region
+    }
+
+    /// The width and height of each cell.
+    pub fn cell_size(&self) -> f32 {
+        self.region.cell_size()
+    }
+
+    /// The world space rectangle that the grid covers.
+    pub fn rect(&self) -> Rect {
+        self.region.rect()
+    }
+
+    /// What happens to fluid at each edge.
+    pub fn boundaries(&self) -> GridBoundaries {
+        self.boundaries
+    }
+
+    /// Wraps the translation back into the grid, across every pair of periodic edges.
+    /// Translations are left alone along axes that don't wrap.
+    pub fn wrap(&self, translation: Vec2) -> Vec2 {
+        let rect = self.rect();
+        let size = rect.size();
+
+        let wraps = self.wraps();
+        let mut translation = translation;
+        if wraps.x {
+            translation.x = rect.min.x + (translation.x - rect.min.x).rem_euclid(size.x);
+        }
+        if wraps.y {
+            translation.y = rect.min.y + (translation.y - rect.min.y).rem_euclid(size.y);
+        }
+        translation
+    }
+
+    /// Which axes wrap around, because both of their edges are periodic.
+    pub fn wraps(&self) -> BVec2 {
+        let GridBoundaries {
+            top,
+            left,
+            right,
+            bottom,
+        } = self.boundaries;
+        BVec2::new(
+            left == Boundary::Periodic && right == Boundary::Periodic,
+            top == Boundary::Periodic && bottom == Boundary::Periodic,
+        )
+    }
+
+    /// The indices of this grid's cells in FluidCells.
+    pub fn cells_range(&self) -> Range<usize> {
+        self.first_cell..self.first_cell + self.cells.len()
+    }
+
+    /// Gets the index in FluidCells of the cell that the translation is inside.
     /// Returns None if the translation is outside the grid.
-    pub fn get(&self, translation: Vec2) -> Option<Entity> {
+    pub fn index(&self, translation: Vec2) -> Option<usize> {
         let index = self.region.translation_to_index(translation)?;
+        Some(self.first_cell + index)
+    }
+
+    /// Gets the cell at the index within this grid.
+    /// Returns None if the index is outside the grid.
+    pub fn get_by_index(&self, index: usize) -> Option<Entity> {
         self.cells.get(index).copied()
     }
+
+    /// Gets the cell that the translation is inside.
+    /// Returns None if the translation is outside the grid.
+    pub fn get(&self, translation: Vec2) -> Option<Entity> {
+        self.get_by_index(self.region.translation_to_index(translation)?)
+    }
+
+    /// Finds the first solid cell along a ray.
+    /// The hit's index is the index in FluidCells, and the entity of the cell is returned alongside it.
+    pub fn raycast_solid(
+        &self,
+        fluid_cells: &FluidCells,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+    ) -> Option<(Entity, GridRayHit)> {
+        let solid = &fluid_cells.solid()[self.cells_range()];
+        let mut hit = self
+            .region
+            .raycast(origin, direction, max_distance, |index| solid[index])?;
+
+        let cell = self.cells[hit.index];
+        hit.index += self.first_cell;
+        Some((cell, hit))
+    }
 }
 
+/// Builds a grid for every window, once windowing is done.
+/// Whenever a window moves or changes size or scale, a window is added or removed, or the grid settings change, every grid is rebuilt.
+/// Fluid, solids, and vents are copied over wherever the new grids overlap the old ones.
+/// The new FluidCells is swapped in by a command, so that it changes at the same time as the grids and cells, and nothing sees a grid that doesn't match it.
 #[system(Update)]
-fn setup(
+fn build(
     mut commands: Commands,
-    cameras: Query<(&Camera, &GlobalTransform)>,
-    mut finished: Local<bool>,
+    cameras: Query<(&Camera, &Transform, &RenderLayers, Option<&GridBoundaries>)>,
+    old_grids: Query<&Grid>,
+    all_cells: Query<Entity, With<Cell>>,
+    old_cells: Query<(
+        Has<Solid>,
+        Option<&ThermalVent>,
+        Option<&Mesh2d>,
+        Option<&MeshMaterial2d<ColorMaterial>>,
+    )>,
     mut windowing_done: EventReader<WindowingDone>,
+    mut window_moved: EventReader<WindowMoved>,
+    mut window_resized: EventReader<WindowResized>,
+    mut window_scale_factor_changed: EventReader<WindowScaleFactorChanged>,
+    mut removed_windows: RemovedComponents<Window>,
+    mut windowing_finished: Local<bool>,
+    mut frames_since_change: Local<Option<u8>>,
     winit_windows: NonSend<WinitWindows>,
+    grid_settings: Res<GridSettings>,
+    old_fluid_cells: Res<FluidCells>,
 ) {
-    if *finished {
-        return;
+    let windowing_done = windowing_done.read().count() != 0;
+    // Every reader is read, so old events don't trigger a rebuild later.
+    let windows_changed = (window_moved.read().count()
+        + window_resized.read().count()
+        + window_scale_factor_changed.read().count()
+        + removed_windows.read().count())
+        != 0
+        || grid_settings.is_changed();
+
+    if windowing_done {
+        *windowing_finished = true;
+        // The windows are already stable, so we don't need to wait.
+        *frames_since_change = Some(u8::MAX);
+    } else if windows_changed && *windowing_finished {
+        *frames_since_change = Some(0);
     }
 
-    if windowing_done.read().next().is_none() {
+    // Resizing can go on for a few frames, so we wait until it stops, instead of rebuilding every frame.
+    let Some(frames) = frames_since_change.as_mut() else {
+        return;
+    };
+    if *frames < 3 {
+        *frames += 1;
         return;
     }
+    *frames_since_change = None;
 
-    *finished = true;
+    let mut fluid_cells = FluidCells::default();
+    let old_grids: Vec<&Grid> = old_grids.iter().collect();
 
-    cameras.iter().for_each(|(camera, global_transform)| {
-        let RenderTarget::Window(WindowRef::Entity(window_entity)) = camera.target else {
-            return;
-        };
+    // This also gets rid of the cells of windows that no longer exist.
+    all_cells.iter().for_each(|cell_entity| {
+        commands.entity(cell_entity).despawn();
+    });
 
-        let Some(window_winit) = winit_windows.get_window(window_entity) else {
-            return;
-        };
+    // The window, region, first cell, and boundaries of each new grid.
+    let mut new_grids: Vec<(Entity, Region, usize, GridBoundaries)> = vec![];
+    // Every new cell, in the same order as FluidCells.
+    let mut new_cells: Vec<Entity> = vec![];
 
-        let size = window_winit
-            .outer_size()
-            .to_logical(window_winit.scale_factor());
-        let height: f32 = size.height;
-        let width = size.width;
+    cameras
+        .iter()
+        .for_each(|(camera, transform, render_layers, boundaries)| {
+            let RenderTarget::Window(WindowRef::Entity(window_entity)) = camera.target else {
+                return;
+            };
 
-        let origin = match camera.ndc_to_world(global_transform, Vec3::new(-1., -1., 0.)) {
-            Some(origin) => origin.xy(),
-            None => {
-                error!("Something contained NaN.");
+            let Some(window_winit) = winit_windows.get_window(window_entity) else {
+                return;
+            };
+
+            let size = window_winit
+                .outer_size()
+                .to_logical(window_winit.scale_factor());
+            let height: f32 = size.height;
+            let width = size.width;
+
+            // Cameras are moved to match their window, and GlobalTransform isn't updated until the end of the frame.
+            // Cameras have no parent, so their Transform is their GlobalTransform.
+            let global_transform = GlobalTransform::from(*transform);
+            let origin = match camera.ndc_to_world(&global_transform, Vec3::new(-1., -1., 0.)) {
+                Some(origin) => origin.xy(),
+                None => {
+                    error!("Something contained NaN.");
+                    return;
+                }
+            };
+
+            let cell_size = grid_settings.cell_size;
+
+            // Divide the height and width by the size, to get the number of cells needed.
+            let grid_height = (height / cell_size).ceil() as usize;
+            let grid_width = (width / cell_size).ceil() as usize;
+            // Minimised windows, and monitors that are still being plugged in, can have no size at all.
+            if grid_width == 0 || grid_height == 0 {
                 return;
             }
-        };
 
-        // Divide the height and width by the size, to get the number of cells needed.
-        let grid_height = (height / Cell::SIZE).ceil() as usize;
-        let grid_width = (width / Cell::SIZE).ceil() as usize;
-
-        let grid_size = UVec2::new(grid_width as u32, grid_height as u32);
-
-        let region = Region {
-            origin,
-            size: grid_size,
-        };
-
-        let cell_entities: Box<[Entity]> = (0..(grid_height * grid_width))
-            .map(|_| commands.spawn_empty().id())
-            .collect();
+            let grid_size = UVec2::new(grid_width as u32, grid_height as u32);
+
+            let region = Region {
+                origin,
+                size: grid_size,
+                cell_size,
+            };
+
+            let water_line = origin.y + height * grid_settings.water_level;
+            let first_cell = fluid_cells.push_grid(&region, water_line);
+
+            let cell_entities: Box<[Entity]> = (0..(grid_height * grid_width))
+                .map(|_| commands.spawn_empty().id())
+                .collect();
+
+            let cells: Box<[Entity]> = cell_entities
+                .iter()
+                .enumerate()
+                .map(|(index, cell_entity)| {
+                    let cell_entity = *cell_entity;
+
+                    // Index is part of the grid, so this will not panic.
+                    let translation = region.index_to_translation(index).unwrap();
+
+                    let mut cell = commands.entity(cell_entity);
+
+                    // Find whichever old cell used to be here, and copy it.
+                    let old = old_grids.iter().find_map(|old_grid| {
+                        let old_index = old_grid.region.translation_to_index(translation)?;
+                        Some((old_grid.first_cell + old_index, old_grid.cells[old_index]))
+                    });
+                    if let Some((old_index, old_cell_entity)) = old {
+                        fluid_cells.copy_cell(first_cell + index, &old_fluid_cells, old_index);
+
+                        if let Ok((solid, thermal_vent, Some(mesh), Some(material))) =
+                            old_cells.get(old_cell_entity)
+                        {
+                            cell.insert((
+                                render_layers.clone(),
+                                mesh.clone(),
+                                material.clone(),
+                                Cell::transform(translation, cell_size),
+                            ));
+                            if solid {
+                                cell.insert(Solid);
+                            }
+                            if let Some(thermal_vent) = thermal_vent {
+                                cell.insert(ThermalVent {
+                                    temperature: thermal_vent.temperature,
+                                });
+                            }
+                        }
+                    }
+
+                    cell_entity
+                })
+                .collect();
+
+            new_cells.extend_from_slice(&cells);
+            let boundaries = boundaries.copied().unwrap_or(grid_settings.boundaries);
+            new_grids.push((window_entity, region, first_cell, boundaries));
+        });
+
+    stitch(&mut fluid_cells, &new_grids);
+    apply_boundaries(&mut fluid_cells, &mut new_grids);
+
+    new_grids
+        .iter()
+        .for_each(|(window_entity, region, first_cell, boundaries)| {
+            let cells = *first_cell..first_cell + region.len();
+
+            commands.entity(*window_entity).insert(Grid {
+                region: *region,
+                cells: new_cells[cells.clone()].into(),
+                first_cell: *first_cell,
+                boundaries: *boundaries,
+            });
+
+            cells.for_each(|index| {
+                commands.entity(new_cells[index]).insert(Cell {
+                    grid: *window_entity,
+
+                    index,
+                    translation: fluid_cells.translation[index],
+                });
+            });
+        });
+
+    commands.insert_resource(fluid_cells);
+}
 
-        let cells = cell_entities
-            .iter()
-            .enumerate()
-            .map(|(index, cell_entity)| {
-                let cell_entity = *cell_entity;
-
-                // Index is part of the grid, so this will not panic.
-                let translation = region.index_to_translation(index).unwrap();
-
-                let top = region
-                    .translation_to_index(translation + Vec2::new(0., Cell::SIZE))
-                    .and_then(|index| cell_entities.get(index).copied());
-                let left = region
-                    .translation_to_index(translation + Vec2::new(-Cell::SIZE, 0.))
-                    .and_then(|index| cell_entities.get(index).copied());
-                let right = region
-                    .translation_to_index(translation + Vec2::new(Cell::SIZE, 0.))
-                    .and_then(|index| cell_entities.get(index).copied());
-                let bottom = region
-                    .translation_to_index(translation + Vec2::new(0., -Cell::SIZE))
-                    .and_then(|index| cell_entities.get(index).copied());
-
-                commands
-                    .entity(cell_entity)
-                    .insert(Cell {
-                        grid: window_entity,
+/// Joins up the edges of grids that are next to each other, so that fluid can flow between monitors.
+/// Edges that aren't next to another grid, like the gaps between monitors of different sizes, are left as walls.
+fn stitch(fluid_cells: &mut FluidCells, grids: &[(Entity, Region, usize, GridBoundaries)]) {
+    // Ordered top, left, right, bottom, same as nearest_4.
+    let directions = [Vec2::Y, Vec2::NEG_X, Vec2::X, Vec2::NEG_Y];
+
+    grids
+        .iter()
+        .enumerate()
+        .for_each(|(grid_index, (_, region, first_cell, _))| {
+            (*first_cell..first_cell + region.len()).for_each(|index| {
+                directions
+                    .iter()
+                    .enumerate()
+                    .for_each(|(direction_index, direction)| {
+                        if fluid_cells.nearest_4[index][direction_index].is_some() {
+                            return;
+                        }
+
+                        let translation =
+                            fluid_cells.translation[index] + *direction * region.cell_size;
+
+                        if let Some(neighbour) = grids
+                            .iter()
+                            .enumerate()
+                            .filter(|(other_grid_index, _)| *other_grid_index != grid_index)
+                            .find_map(|(_, (_, other_region, other_first_cell, _))| {
+                                Some(
+                                    other_first_cell
+                                        + other_region.translation_to_index(translation)?,
+                                )
+                            })
+                        {
+                            link(fluid_cells, index, direction_index, neighbour);
+                        }
+                    });
+            });
+        });
+}
 
-                        index,
-                        translation,
+/// Makes the second cell the first cell's neighbour in the direction, and the first cell the second's in the opposite direction.
+/// Neighbours must always point back at each other, or the pressure solve won't converge, so they are only linked if neither already has a neighbour there.
+fn link(fluid_cells: &mut FluidCells, first: usize, direction: usize, second: usize) {
+    // Because of the order, the opposite direction is always 3 - direction.
+    let opposite = 3 - direction;
+    if fluid_cells.nearest_4[first][direction].is_none()
+        && fluid_cells.nearest_4[second][opposite].is_none()
+    {
+        fluid_cells.nearest_4[first][direction] = Some(second);
+        fluid_cells.nearest_4[second][opposite] = Some(first);
+    }
+}
 
-                        nearest_4: [top, left, right, bottom],
-                    })
-                    .id()
-            })
-            .collect();
+/// Links periodic edges to their opposite edge, and marks which of the remaining edges are open.
+/// This runs after stitching, so edges joined to another grid are left alone.
+/// Any part of a joined edge that isn't next to the other grid is a wall, so that fluid can't leak out of the gaps.
+/// Each grid's boundaries are changed to match.
+fn apply_boundaries(
+    fluid_cells: &mut FluidCells,
+    grids: &mut [(Entity, Region, usize, GridBoundaries)],
+) {
+    grids
+        .iter_mut()
+        .for_each(|(_, region, first_cell, grid_boundaries)| {
+            let first_cell = *first_cell;
+            let width = region.size.x as usize;
+            let height = region.size.y as usize;
+            let cells = first_cell..first_cell + region.len();
+
+            // The cells along each edge, ordered top, left, right, bottom, same as nearest_4.
+            let edge = |direction: usize| -> Vec<usize> {
+                match direction {
+                    0 => ((height - 1) * width..height * width).collect(),
+                    1 => (0..height).map(|y| y * width).collect(),
+                    2 => (0..height).map(|y| y * width + width - 1).collect(),
+                    _ => (0..width).collect(),
+                }
+            };
+            let mut boundaries = grid_boundaries.to_array();
+            (0..4).for_each(|direction| {
+                let joined = edge(direction).into_iter().any(|index| {
+                    fluid_cells.nearest_4[first_cell + index][direction]
+                        .is_some_and(|neighbour| !cells.contains(&neighbour))
+                });
+                if joined {
+                    boundaries[direction] = Boundary::Wall;
+                }
+            });
+            let [top, left, right, bottom] = boundaries;
+            *grid_boundaries = GridBoundaries {
+                top,
+                left,
+                right,
+                bottom,
+            };
+
+            if left == Boundary::Periodic && right == Boundary::Periodic {
+                (0..height).for_each(|y| {
+                    let row = first_cell + y * width;
+                    // Left is 1 in nearest_4.
+                    link(fluid_cells, row, 1, row + width - 1);
+                });
+            }
+            if top == Boundary::Periodic && bottom == Boundary::Periodic {
+                (0..width).for_each(|x| {
+                    let column = first_cell + x;
+                    // Bottom is 3 in nearest_4.
+                    link(fluid_cells, column, 3, column + (height - 1) * width);
+                });
+            }
 
-        commands
-            .entity(window_entity)
-            .insert(Grid { region, cells });
-    });
+            cells.for_each(|index| {
+                fluid_cells.open[index] = std::array::from_fn(|direction| {
+                    fluid_cells.nearest_4[index][direction].is_none()
+                        && boundaries[direction] == Boundary::Open
+                });
+            });
+        });
 }
 
 #[system(Update)]
@@ -189,3 +806,185 @@ fn debug(grids: Query<&Grid>, mut gizmos: Gizmos) {
         gizmos.circle_2d(grid.origin(), 10., Srgba::RED);
     });
 }
+
+#[cfg(test)]
+impl Grid {
+    /// A grid that isn't on any window, for testing systems that use grids.
+    /// Its cells are added to the FluidCells without any water, and their entities are never spawned.
+    pub(super) fn test(
+        origin: Vec2,
+        size: UVec2,
+        cell_size: f32,
+        fluid_cells: &mut FluidCells,
+    ) -> Self {
+        let region = Region {
+            origin,
+            size,
+            cell_size,
+        };
+        let first_cell = fluid_cells.push_grid(&region, f32::NEG_INFINITY);
+
+        Self {
+            region,
+            cells: (0..region.len() as u32).map(Entity::from_raw).collect(),
+            first_cell,
+            boundaries: default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region() -> Region {
+        Region {
+            origin: Vec2::new(10., 20.),
+            size: UVec2::new(4, 3),
+            cell_size: 2.,
+        }
+    }
+
+    #[test]
+    fn contains_and_translation_to_index_agree_at_the_edges() {
+        let region = region();
+        let Rect {
+            min: start,
+            max: end,
+        } = region.rect();
+        let inside = region.cell_size * 0.01;
+
+        [
+            start,
+            start + inside,
+            Vec2::new(start.x, end.y - inside),
+            Vec2::new(end.x - inside, start.y),
+            end - inside,
+            region.origin,
+        ]
+        .into_iter()
+        .for_each(|translation| {
+            assert!(region.contains(translation), "{translation}");
+            assert!(
+                region.translation_to_index(translation).is_some(),
+                "{translation}"
+            );
+        });
+
+        [
+            start - inside,
+            Vec2::new(start.x - inside, start.y + inside),
+            end,
+            Vec2::new(end.x, start.y + inside),
+            Vec2::new(start.x + inside, end.y),
+        ]
+        .into_iter()
+        .for_each(|translation| {
+            assert!(!region.contains(translation), "{translation}");
+            assert!(
+                region.translation_to_index(translation).is_none(),
+                "{translation}"
+            );
+        });
+    }
+
+    #[test]
+    fn translation_to_index_finds_the_closest_cell() {
+        let region = region();
+        let rect = region.rect();
+        let inside = region.cell_size * 0.01;
+
+        assert_eq!(region.translation_to_index(rect.min), Some(0));
+        assert_eq!(
+            region.translation_to_index(rect.max - inside),
+            Some(region.len() - 1)
+        );
+        (0..region.len()).for_each(|index| {
+            let translation = region.index_to_translation(index).unwrap();
+            assert_eq!(region.translation_to_index(translation), Some(index));
+        });
+    }
+
+    #[test]
+    fn empty_regions_have_no_cells() {
+        [UVec2::new(0, 3), UVec2::new(4, 0)]
+            .into_iter()
+            .for_each(|size| {
+                let region = Region { size, ..region() };
+
+                assert!(!region.contains(region.origin));
+                assert_eq!(region.translation_to_index(region.origin), None);
+                assert!(region.bilinear(region.origin).is_none());
+                assert_eq!(
+                    region
+                        .cells_in_rect(Rect::from_center_half_size(region.origin, Vec2::splat(10.)))
+                        .count(),
+                    0
+                );
+                assert_eq!(region.cells_in_circle(region.origin, 10.).count(), 0);
+            });
+    }
+
+    #[test]
+    fn cells_in_circle_finds_cell_centres_inside_it() {
+        let region = region();
+        let centre = region.index_to_translation(5).unwrap();
+
+        // Just past the 4 nearest cell centres, and short of the diagonals.
+        let mut cells: Vec<usize> = region
+            .cells_in_circle(centre, region.cell_size * 1.1)
+            .collect();
+        cells.sort();
+        let mut nearest: Vec<usize> = region.nearest_4(5).into_iter().flatten().collect();
+        nearest.push(5);
+        nearest.sort();
+        assert_eq!(cells, nearest);
+
+        // Only the part of the circle inside the grid has cells.
+        let corner = region.index_to_translation(0).unwrap();
+        let mut cells: Vec<usize> = region
+            .cells_in_circle(corner, region.cell_size * 1.5)
+            .collect();
+        cells.sort();
+        assert_eq!(cells, vec![0, 1, 4, 5]);
+    }
+
+    #[test]
+    fn raycast_solid_stops_at_max_distance() {
+        let region = region();
+        let mut fluid_cells = FluidCells::default();
+        // Another grid goes first, so the grid's cells don't start at 0.
+        Grid::test(Vec2::ZERO, UVec2::new(2, 1), 1., &mut fluid_cells);
+        let grid = Grid::test(
+            region.origin,
+            region.size,
+            region.cell_size,
+            &mut fluid_cells,
+        );
+        // The last cell of the bottom row.
+        fluid_cells.solid[2 + 3] = true;
+
+        // The ray enters the solid 2.5 cells from the centre of the first cell.
+        let origin = region.index_to_translation(0).unwrap();
+        let edge = region.cell_size * 2.5;
+
+        assert!(
+            grid.raycast_solid(&fluid_cells, origin, Vec2::X, edge * 0.99)
+                .is_none()
+        );
+
+        let (cell, hit) = grid
+            .raycast_solid(&fluid_cells, origin, Vec2::X, edge * 1.01)
+            .unwrap();
+        assert_eq!(cell, Entity::from_raw(3));
+        assert_eq!(hit.index, 2 + 3);
+        assert!(hit.translation.distance(origin + Vec2::X * edge) < 1e-4);
+        assert_eq!(hit.normal, Vec2::NEG_X);
+
+        // Rays that leave the grid before reaching a solid hit nothing.
+        assert!(
+            grid.raycast_solid(&fluid_cells, origin, Vec2::NEG_X, f32::INFINITY)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::Liquid;
+}
+
+/// What a water particle is made of.
+/// Liquids don't mix, and denser liquids sink below lighter ones.
+/// This is a component so that buttons can choose a liquid.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Liquid {
+    #[default]
+    FreshWater,
+    /// Salty enough to sink below fresh water and pool at the bottom, like the brine pools in Subnautica's Lost River.
+    Brine,
+    Oil,
+    /// Heavy and thick, like molten rock.
+    Lava,
+}
+
+impl Liquid {
+    pub const ALL: [Self; 4] = [Self::FreshWater, Self::Brine, Self::Oil, Self::Lava];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::FreshWater => "Fresh water",
+            Self::Brine => "Brine",
+            Self::Oil => "Oil",
+            Self::Lava => "Lava",
+        }
+    }
+
+    /// Relative to fresh water.
+    /// These are further apart than in real life, so that the liquids separate quickly.
+    pub fn density(self) -> f32 {
+        match self {
+            Self::FreshWater => 1.,
+            Self::Brine => 1.3,
+            Self::Oil => 0.8,
+            Self::Lava => 2.5,
+        }
+    }
+
+    /// Relative to fresh water.
+    pub fn viscosity(self) -> f32 {
+        match self {
+            Self::FreshWater => 1.,
+            Self::Brine => 1.,
+            Self::Oil => 2.,
+            Self::Lava => 5.,
+        }
+    }
+
+    pub fn colour(self) -> Color {
+        match self {
+            Self::FreshWater => Srgba::new(0., 0., 1., 0.3),
+            Self::Brine => Srgba::new(0.1, 0.45, 0.4, 0.5),
+            Self::Oil => Srgba::new(0.25, 0.2, 0.05, 0.8),
+            Self::Lava => Srgba::new(1., 0.35, 0., 0.9),
+        }
+        .into()
+    }
+}
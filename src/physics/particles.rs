@@ -0,0 +1,329 @@
+// FLIP/PIC is based on Robert Bridson's "Fluid Simulation for Computer Graphics", and Zhu and Bridson's "Animating Sand as a Fluid".
+
+use super::{fluid::FLUID_GRAVITY, surface::FAR};
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{Pooled, WaterParticle};
+}
+
+/// How water particles and the grid share velocity.
+#[init]
+#[derive(Resource)]
+pub struct FlipSettings {
+    /// How much of each particle's velocity change comes from FLIP, rather than PIC.
+    /// PIC takes the grid's velocity, which is stable but smooths splashes away.
+    /// FLIP only takes the change in the grid's velocity, which keeps the detail but gets noisy on its own.
+    pub flip_ratio: f32,
+    /// How far around each particle is water, in cells.
+    /// Anything under half the diagonal of a cell can leave gaps in the water.
+    pub particle_radius: f32,
+}
+
+impl Default for FlipSettings {
+    fn default() -> Self {
+        Self {
+            flip_ratio: 0.95,
+            particle_radius: 0.75,
+        }
+    }
+}
+
+/// A particle of water.
+/// Particles carry the water's velocity between steps, which loses a lot less detail than advecting it through the grid.
+/// They are FLIP/PIC particles, unless they are also an SphParticle.
+/// The transform is only for drawing, and is interpolated between the particle's last 2 translations.
+#[derive(Component)]
+#[require(Transform)]
+pub struct WaterParticle {
+    pub translation: Vec2,
+    previous_translation: Vec2,
+    /// In world units per second.
+    pub velocity: Vec2,
+    pub liquid: Liquid,
+}
+
+impl WaterParticle {
+    pub fn new(translation: Vec2, velocity: Vec2, liquid: Liquid) -> Self {
+        Self {
+            translation,
+            previous_translation: translation,
+            velocity,
+            liquid,
+        }
+    }
+}
+
+/// A water particle that has been taken out of the water, and is hidden until it is poured again.
+/// Reusing particles is cheaper than spawning and despawning them.
+#[derive(Component)]
+pub struct Pooled;
+
+impl Pooled {
+    /// Takes a particle out of the water.
+    pub fn pool(mut particle: EntityCommands) {
+        particle
+            .remove::<(WaterParticle, SphParticle)>()
+            .insert((Pooled, Visibility::Hidden));
+    }
+}
+
+#[system(FluidUpdate::Fluid::StorePrevious)]
+fn store_previous(mut particles: Query<&mut WaterParticle>, fluid_time: Res<FluidTime>) {
+    // Only the first substep, as particles are interpolated between whole steps.
+    if fluid_time.substep() != 0 {
+        return;
+    }
+
+    particles.par_iter_mut().for_each(|mut particle| {
+        particle.previous_translation = particle.translation;
+    });
+}
+
+/// Moves particles smoothly between steps.
+#[system(Update)]
+fn interpolate(mut particles: Query<(&WaterParticle, &mut Transform)>, fluid_time: Res<FluidTime>) {
+    let overstep_fraction = fluid_time.overstep_fraction();
+
+    particles
+        .par_iter_mut()
+        .for_each(|(particle, mut transform)| {
+            let translation = particle
+                .previous_translation
+                .lerp(particle.translation, overstep_fraction);
+            transform.translation.x = translation.x;
+            transform.translation.y = translation.y;
+        });
+}
+
+/// Gives the particles the change in the grid's velocity since they last gave it theirs, and moves them through the grid.
+#[system(FluidUpdate::Fluid::GridToParticles)]
+fn grid_to_particles(
+    mut particles: Query<&mut WaterParticle, Without<SphParticle>>,
+    grids: Query<&Grid>,
+    fluid_cells: Res<FluidCells>,
+    flip_settings: Res<FlipSettings>,
+    fluid_time: Res<FluidTime>,
+) {
+    let time_delta_seconds = fluid_time.delta_secs();
+    let all_grids: Vec<&Grid> = grids.iter().collect();
+
+    particles.par_iter_mut().for_each(|mut particle| {
+        let translation = particle.translation;
+        let mut velocity = particle.velocity;
+
+        let movement = if let Some(grid) = all_grids
+            .iter()
+            .find(|grid| grid.region().contains(translation))
+        {
+            let grid_velocity = fluid_cells.sample(grid, &fluid_cells.velocity, translation);
+            let transferred_velocity =
+                fluid_cells.sample(grid, &fluid_cells.transferred_velocity, translation);
+
+            let pic = grid_velocity;
+            let flip = velocity + grid_velocity - transferred_velocity;
+            // PIC smooths velocity out, like viscosity does, so thicker liquids use more of it.
+            velocity = pic.lerp(flip, flip_settings.flip_ratio / particle.liquid.viscosity());
+
+            // Particles are moved using the grid's velocity, from halfway along the step, which follows curves better than going straight.
+            let midpoint = translation + grid_velocity * time_delta_seconds / 2.;
+            let midpoint_velocity =
+                fluid_cells.sample_across(&all_grids, grid, &fluid_cells.velocity, midpoint);
+            midpoint_velocity * time_delta_seconds
+        } else {
+            // Particles that have left every grid just fall, as fast as the fluid does.
+            velocity += FLUID_GRAVITY * time_delta_seconds;
+            velocity * time_delta_seconds
+        };
+
+        particle.translation = move_particle(
+            &all_grids,
+            &fluid_cells,
+            translation,
+            movement,
+            &mut velocity,
+        );
+        particle.velocity = velocity;
+    });
+}
+
+/// Moves a particle, stopping it at solids and walls.
+/// The velocity has whatever would go into a solid or wall removed.
+/// Returns the particle's new translation.
+pub(super) fn move_particle(
+    grids: &[&Grid],
+    fluid_cells: &FluidCells,
+    translation: Vec2,
+    movement: Vec2,
+    velocity: &mut Vec2,
+) -> Vec2 {
+    let Some(grid) = grids
+        .iter()
+        .find(|grid| grid.region().contains(translation))
+    else {
+        return translation + movement;
+    };
+
+    // A solid was drawn on top of the particle, so it is moved out into the nearest cell that isn't solid.
+    if let Some(index) = grid
+        .index(translation)
+        .filter(|index| fluid_cells.solid[*index])
+    {
+        *velocity = Vec2::ZERO;
+        // The diagonals are tried last, for particles in the corner of a solid.
+        let first_cell = grid.cells_range().start;
+        let diagonals = grid
+            .region()
+            .nearest_8(index - first_cell)
+            .into_iter()
+            .flatten()
+            .map(|neighbour| first_cell + neighbour);
+        return fluid_cells.nearest_4[index]
+            .into_iter()
+            .flatten()
+            .chain(diagonals)
+            .find(|neighbour| !fluid_cells.solid[*neighbour])
+            .map_or(translation, |neighbour| fluid_cells.translation[neighbour]);
+    }
+
+    let mut moved_translation = translation + movement;
+
+    // Particles stop at solids, rather than going through them.
+    if let Some((_, hit)) =
+        grid.raycast_solid(fluid_cells, translation, movement, movement.length())
+    {
+        moved_translation = hit.translation + hit.normal * grid.cell_size() * 0.01;
+        *velocity -= hit.normal * velocity.dot(hit.normal).min(0.);
+    }
+
+    if !grids
+        .iter()
+        .any(|grid| grid.region().contains(moved_translation))
+    {
+        moved_translation = leave_grid(grid, moved_translation, velocity);
+    }
+
+    moved_translation
+}
+
+/// Handles a particle leaving a grid, depending on which edge it left through.
+/// Periodic edges wrap it around, open edges let it go, and every other edge stops it.
+fn leave_grid(grid: &Grid, translation: Vec2, velocity: &mut Vec2) -> Vec2 {
+    let translation = grid.wrap(translation);
+
+    // Particles are kept a little way inside the walls, so that they are still in the grid.
+    let Rect {
+        min: start,
+        max: end,
+    } = grid.rect().inflate(-grid.cell_size() * 0.01);
+
+    let GridBoundaries {
+        top,
+        left,
+        right,
+        bottom,
+    } = grid.boundaries();
+
+    // A periodic edge without a periodic edge opposite it didn't wrap, so it is a wall.
+    let wraps = grid.wraps();
+    let wall = |boundary: Boundary, wraps: bool| boundary != Boundary::Open && !wraps;
+
+    let mut translation = translation;
+    if wall(left, wraps.x) && translation.x < start.x {
+        translation.x = start.x;
+        velocity.x = velocity.x.max(0.);
+    }
+    if wall(right, wraps.x) && translation.x > end.x {
+        translation.x = end.x;
+        velocity.x = velocity.x.min(0.);
+    }
+    if wall(bottom, wraps.y) && translation.y < start.y {
+        translation.y = start.y;
+        velocity.y = velocity.y.max(0.);
+    }
+    if wall(top, wraps.y) && translation.y > end.y {
+        translation.y = end.y;
+        velocity.y = velocity.y.min(0.);
+    }
+    translation
+}
+
+/// Gives the grid the particles' velocity and liquid density, and marks the cells around them as water.
+#[system(FluidUpdate::Fluid::ParticlesToGrid)]
+fn particles_to_grid(
+    particles: Query<&WaterParticle, Without<SphParticle>>,
+    grids: Query<&Grid>,
+    flip_settings: Res<FlipSettings>,
+    mut fluid_cells: ResMut<FluidCells>,
+) {
+    let mut velocity_sum = vec![Vec2::ZERO; fluid_cells.len()];
+    let mut weight_sum = vec![0.; fluid_cells.len()];
+    let mut liquid_density_sum = vec![0.; fluid_cells.len()];
+    let mut particle_level_set = std::mem::take(&mut fluid_cells.particle_level_set);
+    particle_level_set.fill(FAR);
+
+    particles.iter().for_each(|particle| {
+        let translation = particle.translation;
+
+        let Some(grid) = grids
+            .iter()
+            .find(|grid| grid.region().contains(translation))
+        else {
+            return;
+        };
+        let first_cell = grid.cells_range().start;
+
+        grid.region()
+            .bilinear(translation)
+            .into_iter()
+            .flatten()
+            .for_each(|(index, weight)| {
+                velocity_sum[first_cell + index] += particle.velocity * weight;
+                weight_sum[first_cell + index] += weight;
+                liquid_density_sum[first_cell + index] += particle.liquid.density() * weight;
+            });
+
+        // A cell further out is included, so that the level set around the particle is correct too.
+        let radius = flip_settings.particle_radius * grid.cell_size();
+        grid.region()
+            .cells_in_circle(translation, radius + grid.cell_size())
+            .for_each(|index| {
+                let index = first_cell + index;
+                let distance = fluid_cells.translation[index].distance(translation) - radius;
+                particle_level_set[index] = particle_level_set[index].min(distance);
+            });
+    });
+
+    fluid_cells.particle_level_set = particle_level_set;
+
+    // Cells without any particles keep the velocity they were advected to.
+    let FluidCells {
+        solid,
+        velocity,
+        transferred_velocity,
+        liquid_density,
+        ..
+    } = &mut *fluid_cells;
+
+    velocity
+        .iter_mut()
+        .zip(liquid_density.iter_mut())
+        .zip(velocity_sum.into_iter().zip(weight_sum))
+        .zip(liquid_density_sum)
+        .enumerate()
+        .for_each(
+            |(
+                index,
+                (((velocity, liquid_density), (velocity_sum, weight_sum)), liquid_density_sum),
+            )| {
+                if weight_sum > 0. && !solid[index] {
+                    *velocity = velocity_sum / weight_sum;
+                    *liquid_density = liquid_density_sum / weight_sum;
+                } else {
+                    *liquid_density = 1.;
+                }
+            },
+        );
+
+    transferred_velocity.copy_from_slice(velocity);
+}
@@ -1,3 +1,4 @@
+use super::fluid::AMBIENT_TEMPERATURE;
 use crate::prelude::*;
 
 pub mod prelude {
@@ -27,7 +28,7 @@ struct MeshesAndMaterials {
 #[system(Startup)]
 fn create_meshes_and_materials(mut meshes: ResMut<Assets<Mesh>>, mut commands: Commands) {
     commands.insert_resource(MeshesAndMaterials {
-        square_mesh: meshes.add(Rectangle::new(Cell::SIZE, Cell::SIZE)),
+        square_mesh: meshes.add(Rectangle::new(1., 1.)),
         colour_materials: default(),
     });
 }
@@ -72,18 +73,71 @@ fn render(
             info!("New colour material.");
         }
 
-        commands.entity(cell_entity).insert((
-            Solid,
-            render_layers.clone(),
-            Mesh2d(meshes_and_materials.square_mesh.clone()),
-            MeshMaterial2d(
-                meshes_and_materials
-                    .colour_materials
-                    .get(&colour_not_nan)
-                    .unwrap()
-                    .clone(),
-            ),
-            Transform::from_translation(Vec3::new(cell.translation.x, cell.translation.y, 0.)),
-        ));
+        // A solid can't also be a vent, so painting over one replaces it.
+        commands
+            .entity(cell_entity)
+            .remove::<ThermalVent>()
+            .insert((
+                Solid,
+                render_layers.clone(),
+                Mesh2d(meshes_and_materials.square_mesh.clone()),
+                MeshMaterial2d(
+                    meshes_and_materials
+                        .colour_materials
+                        .get(&colour_not_nan)
+                        .unwrap()
+                        .clone(),
+                ),
+                Cell::transform(cell.translation, grid.cell_size()),
+            ));
+    });
+}
+
+/// Any fluid caught inside a newly placed solid is pushed out into the fluid around it.
+#[system(Update)]
+fn push_out_trapped_fluid(
+    solids: Query<&Cell, Added<Solid>>,
+    grids: Query<&Grid>,
+    mut fluid_cells: ResMut<FluidCells>,
+) {
+    solids.iter().for_each(|cell| {
+        let index = cell.index;
+        let Ok(grid) = grids.get(cell.grid) else {
+            return;
+        };
+
+        // Rebuilt grids copy solids over, and there can't be any fluid trapped in something that was already solid.
+        if fluid_cells.solid[index] {
+            return;
+        }
+        fluid_cells.solid[index] = true;
+        // A solid can't also be a vent.
+        fluid_cells.heat_source[index] = None;
+
+        // Ordered top, left, right, bottom, same as nearest_4.
+        let directions = [Vec2::Y, Vec2::NEG_X, Vec2::X, Vec2::NEG_Y];
+
+        let escape_routes: Vec<(usize, Vec2)> = fluid_cells.nearest_4[index]
+            .into_iter()
+            .zip(directions)
+            .filter_map(|(neighbour, direction)| {
+                neighbour
+                    .filter(|neighbour| !fluid_cells.solid[*neighbour])
+                    .map(|neighbour| (neighbour, direction))
+            })
+            .collect();
+
+        let trapped_dye = fluid_cells.dye[index];
+        let share = 1. / escape_routes.len() as f32;
+        escape_routes.iter().for_each(|(neighbour, direction)| {
+            // Moving a cell's worth of fluid in one second.
+            fluid_cells.velocity[*neighbour] += *direction * grid.cell_size() * share;
+            fluid_cells.dye[*neighbour] = fluid_cells.dye[*neighbour].mix(&trapped_dye, share);
+        });
+
+        fluid_cells.velocity[index] = Vec2::ZERO;
+        fluid_cells.pressure[index] = 0.;
+        fluid_cells.dye[index] = LinearRgba::NONE;
+        fluid_cells.temperature[index] = AMBIENT_TEMPERATURE;
     });
 }
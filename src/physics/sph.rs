@@ -0,0 +1,383 @@
+// All the SPH physics is taken from https://www.cs.cornell.edu/~bindel/class/cs5220-f11/code/sph.pdf
+
+use super::{fluid::par_for_each_mut, particles::move_particle};
+use crate::prelude::*;
+use std::f32::consts::PI;
+
+#[cfg(test)]
+mod dam_break;
+
+pub mod prelude {
+    pub use super::{SphParticle, SphPreset, SphSettings};
+}
+
+/// The mass of each fresh water particle.
+/// Other liquids are heavier or lighter by their density.
+/// Everything else is scaled to match, so this only changes the units that densities are in.
+const MASS: f32 = 1.;
+
+/// How SPH water behaves.
+/// The constants that SPH uses are all worked out from these, so that changing one doesn't throw the others off.
+#[init]
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SphSettings {
+    /// How far apart particles are at rest, in world units.
+    /// Particles push on each other from up to twice this far away.
+    pub spacing: f32,
+    /// How fast pressure spreads through the water, in world units per second.
+    /// It should be about 10 times faster than the water ever moves, or the water squashes like a sponge.
+    /// Faster is stiffer, but needs more substeps.
+    pub speed_of_sound: f32,
+    /// How thick fresh water is.
+    /// Thicker water settles faster, but flows like syrup.
+    /// Other liquids are thicker or thinner by their viscosity.
+    pub viscosity: f32,
+}
+
+impl Default for SphSettings {
+    fn default() -> Self {
+        SphPreset::Water.into()
+    }
+}
+
+/// SphSettings that have been tuned to look right.
+/// This is a component so that buttons can choose a preset.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub enum SphPreset {
+    Water,
+    /// Thinner than water, and made of smaller particles, so it sloshes around for longer and breaks into finer drops.
+    Splashy,
+    /// Thicker than water, and made of bigger particles, which are cheaper and hold together anyway.
+    Syrup,
+}
+
+impl SphPreset {
+    pub const ALL: [Self; 3] = [Self::Water, Self::Splashy, Self::Syrup];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Water => "Water",
+            Self::Splashy => "Splashy",
+            Self::Syrup => "Syrup",
+        }
+    }
+}
+
+impl From<SphPreset> for SphSettings {
+    fn from(preset: SphPreset) -> Self {
+        match preset {
+            SphPreset::Water => Self {
+                spacing: 15.,
+                speed_of_sound: 1000.,
+                viscosity: 10.,
+            },
+            SphPreset::Splashy => Self {
+                spacing: 12.,
+                speed_of_sound: 1000.,
+                viscosity: 4.,
+            },
+            SphPreset::Syrup => Self {
+                spacing: 20.,
+                speed_of_sound: 1000.,
+                viscosity: 40.,
+            },
+        }
+    }
+}
+
+/// The constants used by the kernels, worked out from SphSettings.
+#[derive(Clone, Copy)]
+struct Constants {
+    /// How far away particles affect each other.
+    h: f32,
+    h2: f32,
+    /// The density fresh water particles settle at.
+    rho0: f32,
+    c: f32,
+    c0: f32,
+    cp: f32,
+    cv: f32,
+    /// The longest a substep can be before pressure goes unstable.
+    max_pressure_substep_seconds: f32,
+    /// The longest a substep can be before fresh water's viscosity goes unstable.
+    max_viscous_substep_seconds: f32,
+}
+
+impl SphSettings {
+    fn constants(&self) -> Constants {
+        let h = self.spacing * 2.;
+        let h2 = h * h;
+        let h8 = (h2 * h2) * (h2 * h2);
+
+        let c = 4. * MASS / PI / h8;
+        let c0 = MASS / PI / (h2 * h2);
+        // The square of the speed of sound is how much the pressure changes with density.
+        let k = self.speed_of_sound * self.speed_of_sound;
+        let cp = 15. * k;
+        let cv = -40. * self.viscosity;
+
+        // The density of fresh water particles that are exactly spacing apart, in a square grid.
+        let neighbours = (h / self.spacing).ceil() as i32;
+        let rho0 = (-neighbours..=neighbours)
+            .flat_map(|y| (-neighbours..=neighbours).map(move |x| IVec2::new(x, y)))
+            .map(|offset| {
+                let r2 = (offset.as_vec2() * self.spacing).length_squared();
+                let z = (h2 - r2).max(0.);
+                c * z * z * z
+            })
+            .sum();
+
+        // Nothing, not even a pressure wave, should cross more than part of h in 1 substep.
+        let max_pressure_substep_seconds = 0.4 * h / self.speed_of_sound;
+        // Viscosity slows particles down relative to each other, and overshoots if a substep slows them by more than their difference.
+        // Particles at rest have about 3 neighbours' worth of closeness, so 6 gives room for squashing.
+        let viscous_rate = -cv * c0 / (rho0 * rho0) * 6.;
+        let max_viscous_substep_seconds = 0.5 / viscous_rate;
+
+        Constants {
+            h,
+            h2,
+            rho0,
+            c,
+            c0,
+            cp,
+            cv,
+            max_pressure_substep_seconds,
+            max_viscous_substep_seconds,
+        }
+    }
+}
+
+/// Turns a water particle into an SPH particle, which is pushed around by the particles near it, rather than by the grid.
+#[derive(Component, Default)]
+pub struct SphParticle {
+    pub density: f32,
+}
+
+/// The state of a particle, gathered up so that particles can see each other.
+#[derive(Clone, Copy)]
+struct Particle {
+    translation: Vec2,
+    velocity: Vec2,
+    density: f32,
+    /// The density of the particle's liquid, relative to fresh water.
+    /// This is how much heavier the particle is than MASS.
+    liquid_density: f32,
+    /// The viscosity of the particle's liquid, relative to fresh water.
+    liquid_viscosity: f32,
+}
+
+impl Particle {
+    fn new(translation: Vec2, velocity: Vec2, density: f32, liquid: Liquid) -> Self {
+        Self {
+            translation,
+            velocity,
+            density,
+            liquid_density: liquid.density(),
+            liquid_viscosity: liquid.viscosity(),
+        }
+    }
+}
+
+/// Finds particles near each other, by sorting them into square buckets h wide.
+/// Any particle within h of another is in the same bucket, or 1 of the 8 around it.
+struct SpatialHash {
+    h: f32,
+    buckets: HashMap<IVec2, Vec<usize>>,
+}
+
+impl SpatialHash {
+    fn new(particles: &[Particle], h: f32) -> Self {
+        let mut spatial_hash = Self {
+            h,
+            buckets: HashMap::default(),
+        };
+        particles.iter().enumerate().for_each(|(index, particle)| {
+            let bucket = spatial_hash.bucket(particle.translation);
+            spatial_hash.buckets.entry(bucket).or_default().push(index);
+        });
+        spatial_hash
+    }
+
+    fn bucket(&self, translation: Vec2) -> IVec2 {
+        (translation / self.h).floor().as_ivec2()
+    }
+
+    /// Every particle that could be within h of the translation, including any particle at the translation.
+    fn near(&self, translation: Vec2) -> impl Iterator<Item = usize> + '_ {
+        let bucket = self.bucket(translation);
+        (-1..=1)
+            .flat_map(move |y| (-1..=1).map(move |x| bucket + IVec2::new(x, y)))
+            .filter_map(|bucket| self.buckets.get(&bucket))
+            .flatten()
+            .copied()
+    }
+}
+
+/// Calculates the density at every particle.
+/// Every particle includes itself, and each pair sees each other the same way.
+/// Each particle's density is its own mass times how closely packed it is, so that liquids of different densities don't blur together where they meet.
+fn densities(particles: &mut [Particle], spatial_hash: &SpatialHash, constants: &Constants) {
+    let mut densities = vec![0.; particles.len()];
+
+    par_for_each_mut(&mut densities, |index, density| {
+        let translation = particles[index].translation;
+
+        let packing: f32 = spatial_hash
+            .near(translation)
+            .map(|neighbour| {
+                let r2 = translation.distance_squared(particles[neighbour].translation);
+                let z = constants.h2 - r2;
+                if z > 0. { constants.c * z * z * z } else { 0. }
+            })
+            .sum();
+        *density = particles[index].liquid_density * packing;
+    });
+
+    particles
+        .iter_mut()
+        .zip(densities)
+        .for_each(|(particle, density)| particle.density = density);
+}
+
+/// Calculates the acceleration of every particle, from pressure and viscosity.
+/// The force on each pair is equal and opposite, so momentum is kept.
+fn accelerations(
+    particles: &[Particle],
+    spatial_hash: &SpatialHash,
+    constants: &Constants,
+) -> Vec<Vec2> {
+    let mut accelerations = vec![Vec2::ZERO; particles.len()];
+
+    par_for_each_mut(&mut accelerations, |index, acceleration| {
+        let particle = particles[index];
+
+        *acceleration = spatial_hash
+            .near(particle.translation)
+            .filter(|neighbour| *neighbour != index)
+            .map(|neighbour| {
+                let neighbour = particles[neighbour];
+                let dx = particle.translation - neighbour.translation;
+
+                let r2 = dx.length_squared();
+                // Particles right on top of each other have no direction to push in.
+                if r2 >= constants.h2 || r2 == 0. {
+                    return Vec2::ZERO;
+                }
+
+                let q = r2.sqrt() / constants.h;
+                let u = 1. - q;
+                let w0 = constants.c0 * neighbour.liquid_density * u
+                    / particle.density
+                    / neighbour.density;
+                // Water that is spread out doesn't pull itself back together, otherwise particles clump up.
+                // Each liquid has its own rest density, which is what makes lighter liquids float on heavier ones.
+                let pressure = (particle.density - particle.liquid_density * constants.rho0
+                    + neighbour.density
+                    - neighbour.liquid_density * constants.rho0)
+                    .max(0.);
+                let wp = w0 * constants.cp * pressure * u / q;
+                let viscosity = (particle.liquid_viscosity + neighbour.liquid_viscosity) / 2.;
+                let wv = w0 * constants.cv * viscosity;
+
+                let dv = particle.velocity - neighbour.velocity;
+                wp * dx + wv * dv
+            })
+            .sum();
+    });
+
+    accelerations
+}
+
+/// Steps the particles forward, splitting the step up as much as it takes to stay stable.
+/// Move through is used to move each particle, so that it can be stopped by whatever it hits.
+fn step(
+    particles: &mut [Particle],
+    sph_settings: &SphSettings,
+    gravity: Vec2,
+    time_delta_seconds: f32,
+    move_through: impl Fn(Vec2, Vec2, &mut Vec2) -> Vec2 + Send + Sync,
+) {
+    let constants = sph_settings.constants();
+
+    // Light particles are pushed around the most by heavy ones, and by thick ones, so they set how short substeps have to be.
+    let (lightest, heaviest, thickest) = particles.iter().fold(
+        (f32::INFINITY, 0_f32, 0_f32),
+        |(lightest, heaviest, thickest), particle| {
+            (
+                lightest.min(particle.liquid_density),
+                heaviest.max(particle.liquid_density),
+                thickest.max(particle.liquid_viscosity / particle.liquid_density),
+            )
+        },
+    );
+    let max_substep_seconds = (constants.max_pressure_substep_seconds
+        / ((1. + heaviest / lightest) / 2.).sqrt())
+    .min(constants.max_viscous_substep_seconds / thickest);
+
+    let substeps = (time_delta_seconds / max_substep_seconds).ceil().max(1.);
+    let substep_seconds = time_delta_seconds / substeps;
+
+    (0..substeps as u32).for_each(|_| {
+        let spatial_hash = SpatialHash::new(particles, constants.h);
+        densities(particles, &spatial_hash, &constants);
+        let accelerations = accelerations(particles, &spatial_hash, &constants);
+
+        par_for_each_mut(particles, |index, particle| {
+            particle.velocity += (accelerations[index] + gravity) * substep_seconds;
+            let mut velocity = particle.velocity;
+            particle.translation = move_through(
+                particle.translation,
+                particle.velocity * substep_seconds,
+                &mut velocity,
+            );
+            particle.velocity = velocity;
+        });
+    });
+}
+
+#[system(FluidUpdate::Fluid::Sph)]
+fn sph(
+    mut sph_particles: Query<(&mut WaterParticle, &mut SphParticle)>,
+    grids: Query<&Grid>,
+    fluid_cells: Res<FluidCells>,
+    sph_settings: Res<SphSettings>,
+    gravity: Res<Gravity>,
+    fluid_time: Res<FluidTime>,
+) {
+    let mut particles: Vec<Particle> = sph_particles
+        .iter()
+        .map(|(water_particle, sph_particle)| {
+            Particle::new(
+                water_particle.translation,
+                water_particle.velocity,
+                sph_particle.density,
+                water_particle.liquid,
+            )
+        })
+        .collect();
+
+    if particles.is_empty() {
+        return;
+    }
+
+    let all_grids: Vec<&Grid> = grids.iter().collect();
+
+    step(
+        &mut particles,
+        &sph_settings,
+        gravity.0,
+        fluid_time.delta_secs(),
+        |translation, movement, velocity| {
+            move_particle(&all_grids, &fluid_cells, translation, movement, velocity)
+        },
+    );
+
+    sph_particles.iter_mut().zip(particles).for_each(
+        |((mut water_particle, mut sph_particle), particle)| {
+            water_particle.translation = particle.translation;
+            water_particle.velocity = particle.velocity;
+            sph_particle.density = particle.density;
+        },
+    );
+}
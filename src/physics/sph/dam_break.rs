@@ -0,0 +1,142 @@
+// A column of water is let go in a box, which is a standard test for fluid simulations.
+// It runs without the rest of the game, so that it gives the same result every time.
+
+use super::{Particle, SphPreset, SphSettings, step};
+use crate::prelude::*;
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+const BOX_SIZE: Vec2 = Vec2::new(600., 600.);
+const COLUMN_SIZE: Vec2 = Vec2::new(200., 400.);
+/// The same as the game's gravity.
+const GRAVITY: Vec2 = Vec2::new(0., -100.);
+
+/// By this time, the column should have fallen and spread out.
+const COLLAPSE_SECONDS: f32 = 3.;
+/// By this time, the water should be still.
+const SETTLE_SECONDS: f32 = 30.;
+/// How fast the water can still be moving once it has settled, on average.
+const SETTLED_SPEED: f32 = 2.;
+/// How far the settled water's density can be from the rest density, as a fraction of it.
+const DENSITY_TOLERANCE: f32 = 0.05;
+
+#[test]
+fn dam_break() {
+    // SPH spreads its work across threads, which normally the game sets up.
+    ComputeTaskPool::get_or_init(TaskPool::default);
+
+    SphPreset::ALL.into_iter().for_each(dam_break_preset);
+}
+
+fn dam_break_preset(preset: SphPreset) {
+    let sph_settings = SphSettings::from(preset);
+    let rest_density = sph_settings.constants().rho0;
+    let time_delta_seconds = 1. / FluidTimestep::default().rate;
+
+    // The column is filled with particles at rest spacing, in the bottom left of the box.
+    let columns = (COLUMN_SIZE / sph_settings.spacing).as_uvec2();
+    let mut particles: Vec<Particle> = (0..columns.y)
+        .flat_map(|y| (0..columns.x).map(move |x| UVec2::new(x, y)))
+        .map(|coordinates| {
+            Particle::new(
+                (coordinates.as_vec2() + 0.5) * sph_settings.spacing,
+                Vec2::ZERO,
+                rest_density,
+                Liquid::FreshWater,
+            )
+        })
+        .collect();
+    let particle_count = particles.len();
+
+    let move_through = |translation: Vec2, movement: Vec2, velocity: &mut Vec2| {
+        let moved_translation = translation + movement;
+        let clamped_translation = moved_translation.clamp(Vec2::ZERO, BOX_SIZE);
+        // Whatever would go through a wall is stopped.
+        if clamped_translation.x != moved_translation.x {
+            velocity.x = 0.;
+        }
+        if clamped_translation.y != moved_translation.y {
+            velocity.y = 0.;
+        }
+        clamped_translation
+    };
+
+    let steps = |seconds: f32| (seconds / time_delta_seconds).round() as u32;
+
+    (0..steps(COLLAPSE_SECONDS)).for_each(|_| {
+        step(
+            &mut particles,
+            &sph_settings,
+            GRAVITY,
+            time_delta_seconds,
+            move_through,
+        );
+        check_particles(preset, &particles, particle_count);
+    });
+
+    let front = particles
+        .iter()
+        .map(|particle| particle.translation.x)
+        .fold(0., f32::max);
+    let top = particles
+        .iter()
+        .map(|particle| particle.translation.y)
+        .fold(0., f32::max);
+    assert!(
+        front >= COLUMN_SIZE.x * 2. && top <= COLUMN_SIZE.y * 0.75,
+        "{preset:?}: the column didn't collapse, its front is at {front} and its top is at {top}"
+    );
+
+    (steps(COLLAPSE_SECONDS)..steps(SETTLE_SECONDS)).for_each(|_| {
+        step(
+            &mut particles,
+            &sph_settings,
+            GRAVITY,
+            time_delta_seconds,
+            move_through,
+        );
+        check_particles(preset, &particles, particle_count);
+    });
+
+    let mean_squared_speed = particles
+        .iter()
+        .map(|particle| particle.velocity.length_squared())
+        .sum::<f32>()
+        / particle_count as f32;
+    let speed = mean_squared_speed.sqrt();
+    assert!(
+        speed <= SETTLED_SPEED,
+        "{preset:?}: the water didn't settle, it is moving at {speed}"
+    );
+
+    // The median is used, as the particles on the surface have fewer neighbours, and so always have a lower density.
+    let mut densities: Vec<f32> = particles.iter().map(|particle| particle.density).collect();
+    densities.sort_by(f32::total_cmp);
+    let density = densities[densities.len() / 2] / rest_density;
+    assert!(
+        (density - 1.).abs() <= DENSITY_TOLERANCE,
+        "{preset:?}: the water settled at {density} times the rest density"
+    );
+}
+
+/// Makes sure every particle is still in the box, and none have exploded.
+fn check_particles(preset: SphPreset, particles: &[Particle], particle_count: usize) {
+    if let Some(particle) = particles
+        .iter()
+        .find(|particle| !particle.translation.is_finite() || !particle.velocity.is_finite())
+    {
+        panic!(
+            "{preset:?}: a particle exploded to {} at {}",
+            particle.translation, particle.velocity
+        );
+    }
+
+    let box_rect = Rect::from_corners(Vec2::ZERO, BOX_SIZE);
+    let inside = particles
+        .iter()
+        .filter(|particle| box_rect.contains(particle.translation))
+        .count();
+    assert_eq!(
+        inside, particle_count,
+        "{preset:?}: there were {particle_count} particles in the box, but now there are {inside}"
+    );
+}
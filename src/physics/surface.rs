@@ -0,0 +1,162 @@
+// The level set method is based on Robert Bridson's "Fluid Simulation for Computer Graphics".
+
+use super::fluid::par_for_each_mut;
+use crate::prelude::*;
+
+/// How many cells away from the surface the level set is kept accurate.
+/// Past this, all that matters is whether a cell is water or air.
+const REDISTANCE_CELLS: usize = 8;
+
+/// How many cells of air next to the water are given the water's velocity.
+const EXTRAPOLATE_CELLS: usize = 4;
+
+/// The level set of cells too far from the surface for redistancing to reach.
+/// This isn't infinity, because infinity times a bilinear weight of 0 is NaN.
+pub(super) const FAR: f32 = 1e6;
+
+/// Air has no velocity of its own, but the water's surface is advected using the velocity on both sides of it.
+/// So the velocity of the water is spread into the air next to it.
+#[system(FluidUpdate::Fluid::Extrapolate)]
+fn extrapolate_velocity(mut fluid_cells: ResMut<FluidCells>) {
+    let mut known: Vec<bool> = (0..fluid_cells.len())
+        .map(|index| fluid_cells.liquid(index))
+        .collect();
+    let mut extrapolated: Vec<Option<Vec2>> = vec![None; known.len()];
+
+    // Each pass spreads the velocity 1 cell further out.
+    (0..EXTRAPOLATE_CELLS).for_each(|_| {
+        par_for_each_mut(&mut extrapolated, |index, extrapolated| {
+            *extrapolated = None;
+            if known[index] || fluid_cells.solid[index] {
+                return;
+            }
+
+            let (sum, count) = fluid_cells.nearest_4[index]
+                .into_iter()
+                .flatten()
+                .filter(|neighbour| known[*neighbour])
+                .fold((Vec2::ZERO, 0.), |(sum, count), neighbour| {
+                    (sum + fluid_cells.velocity[neighbour], count + 1.)
+                });
+
+            if count != 0. {
+                *extrapolated = Some(sum / count);
+            }
+        });
+
+        extrapolated
+            .iter()
+            .enumerate()
+            .for_each(|(index, extrapolated)| {
+                if let Some(extrapolated) = extrapolated {
+                    fluid_cells.velocity[index] = *extrapolated;
+                    known[index] = true;
+                }
+            });
+    });
+
+    // Air that is too far from the water to be reached doesn't matter, so it is left still.
+    fluid_cells
+        .velocity
+        .iter_mut()
+        .zip(&known)
+        .filter(|(_, known)| !**known)
+        .for_each(|(velocity, _)| *velocity = Vec2::ZERO);
+}
+
+/// Moves the water's surface along with the velocity, the same way the velocity is advected.
+#[system(FluidUpdate::Fluid::Advect)]
+fn advect_level_set(
+    mut fluid_cells: ResMut<FluidCells>,
+    grids: Query<&Grid>,
+    fluid_time: Res<FluidTime>,
+) {
+    let time_delta_seconds = fluid_time.delta_secs();
+
+    let mut advected_level_set = std::mem::take(&mut fluid_cells.level_set_update);
+    let all_grids: Vec<&Grid> = grids.iter().collect();
+
+    grids.iter().for_each(|grid| {
+        let range = grid.cells_range();
+        let first_cell = range.start;
+
+        par_for_each_mut(
+            &mut advected_level_set[range],
+            |index, advected_level_set| {
+                let index = first_cell + index;
+                // Solids don't move, and are redistanced from the cells around them.
+                if fluid_cells.solid[index] {
+                    *advected_level_set = fluid_cells.level_set[index];
+                    return;
+                }
+
+                let previous_translation = fluid_cells.translation[index]
+                    - fluid_cells.velocity[index] * time_delta_seconds;
+
+                *advected_level_set = fluid_cells.sample_across(
+                    &all_grids,
+                    grid,
+                    &fluid_cells.level_set,
+                    previous_translation,
+                );
+            },
+        );
+    });
+
+    fluid_cells.level_set_update =
+        std::mem::replace(&mut fluid_cells.level_set, advected_level_set);
+}
+
+/// Advection stretches and squashes the level set, so it stops being the distance to the surface.
+/// This fixes it, by keeping the cells right next to the surface, and working outwards from them.
+#[system(FluidUpdate::Fluid::Redistance)]
+fn redistance(mut fluid_cells: ResMut<FluidCells>, grids: Query<&Grid>) {
+    let mut level_set = std::mem::take(&mut fluid_cells.level_set_update);
+
+    // Cells next to the surface are the only ones that know where it is, so they are kept.
+    par_for_each_mut(&mut level_set, |index, level_set| {
+        let center = fluid_cells.level_set[index];
+        let next_to_surface = fluid_cells.nearest_4[index]
+            .into_iter()
+            .flatten()
+            .filter(|neighbour| !fluid_cells.solid[*neighbour])
+            .any(|neighbour| (fluid_cells.level_set[neighbour] < 0.) != (center < 0.));
+
+        *level_set = if next_to_surface || fluid_cells.solid[index] {
+            center
+        } else {
+            FAR.copysign(center)
+        };
+    });
+
+    // Each pass moves the distance 1 cell further out.
+    (0..REDISTANCE_CELLS).for_each(|_| {
+        fluid_cells.level_set.copy_from_slice(&level_set);
+
+        grids.iter().for_each(|grid| {
+            let range = grid.cells_range();
+            let first_cell = range.start;
+            // Neighbours can be on the other side of a periodic edge, so the distance between them is always taken to be 1 cell.
+            let cell_size = grid.cell_size();
+
+            par_for_each_mut(&mut level_set[range], |index, level_set| {
+                let index = first_cell + index;
+                if fluid_cells.solid[index] {
+                    return;
+                }
+
+                let center = fluid_cells.level_set[index];
+                let closest = fluid_cells.nearest_4[index]
+                    .into_iter()
+                    .flatten()
+                    .filter(|neighbour| !fluid_cells.solid[*neighbour])
+                    .map(|neighbour| fluid_cells.level_set[neighbour].abs() + cell_size)
+                    .fold(center.abs(), f32::min);
+
+                *level_set = closest.copysign(center);
+            });
+        });
+    });
+
+    fluid_cells.level_set_update = std::mem::replace(&mut fluid_cells.level_set, level_set);
+}
@@ -0,0 +1,88 @@
+use crate::prelude::*;
+
+pub mod prelude {
+    pub use super::{SetThermalVent, ThermalVent};
+}
+
+/// A cell that constantly heats the fluid inside it.
+/// Fluid can still flow through it, which carries the heat away.
+#[derive(Component)]
+pub struct ThermalVent {
+    pub temperature: f32,
+}
+
+impl ThermalVent {
+    /// Hot enough to boil, if we weren't deep underwater.
+    pub const DEFAULT_TEMPERATURE: f32 = 400.;
+}
+
+#[init]
+#[derive(Event)]
+pub struct SetThermalVent {
+    pub window: Entity,
+    pub translation: Vec2,
+    pub temperature: f32,
+}
+
+/// Handles needed for rendering.
+#[derive(Resource)]
+struct MeshAndMaterial {
+    square_mesh: Handle<Mesh>,
+    colour_material: Handle<ColorMaterial>,
+}
+
+#[system(Startup)]
+fn create_mesh_and_material(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut commands: Commands,
+) {
+    commands.insert_resource(MeshAndMaterial {
+        square_mesh: meshes.add(Rectangle::new(1., 1.)),
+        colour_material: materials.add(ColorMaterial::from_color(Srgba::rgb(0.8, 0.3, 0.1))),
+    });
+}
+
+#[system(Update)]
+fn render(
+    mut set_thermal_vent: EventReader<SetThermalVent>,
+    grids: Query<(&Grid, &RenderLayers)>,
+    cells: Query<&Cell, Without<Solid>>,
+    mesh_and_material: Res<MeshAndMaterial>,
+    mut commands: Commands,
+) {
+    set_thermal_vent.read().for_each(|set_thermal_vent| {
+        let Ok((grid, render_layers)) = grids.get(set_thermal_vent.window) else {
+            return;
+        };
+
+        let Some(cell_entity) = grid.get(set_thermal_vent.translation) else {
+            return;
+        };
+
+        let Ok(cell) = cells.get(cell_entity) else {
+            return;
+        };
+
+        commands.entity(cell_entity).insert((
+            ThermalVent {
+                temperature: set_thermal_vent.temperature,
+            },
+            render_layers.clone(),
+            Mesh2d(mesh_and_material.square_mesh.clone()),
+            MeshMaterial2d(mesh_and_material.colour_material.clone()),
+            Cell::transform(cell.translation, grid.cell_size()),
+        ));
+    });
+}
+
+/// Lets the fluid know about new vents.
+#[system(Update)]
+fn heat_source(
+    thermal_vents: Query<(&Cell, &ThermalVent), Added<ThermalVent>>,
+    mut fluid_cells: ResMut<FluidCells>,
+) {
+    thermal_vents.iter().for_each(|(cell, thermal_vent)| {
+        fluid_cells.heat_source[cell.index] = Some(thermal_vent.temperature);
+    });
+}
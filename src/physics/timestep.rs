@@ -0,0 +1,102 @@
+use crate::prelude::*;
+use bevy::ecs::schedule::ScheduleLabel;
+
+pub mod prelude {
+    pub use super::{FluidTime, FluidTimestep, FluidUpdate};
+}
+
+/// The schedule that steps the fluid.
+/// It is run from Update, as many times as it takes to keep up with real time.
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FluidUpdate;
+
+/// How often the fluid is stepped.
+/// Every step is the same length, so the fluid behaves the same no matter the frame rate.
+#[init]
+#[derive(Resource)]
+pub struct FluidTimestep {
+    /// How many steps there are per second.
+    /// Set with `--fluid-rate` on the command line.
+    pub rate: f32,
+    /// How many smaller steps each step is split into.
+    /// Set with `--fluid-substeps` on the command line.
+    /// More substeps are more stable, but cost more.
+    pub substeps: u32,
+    /// The most steps that are run in 1 frame.
+    /// When a frame takes too long, the fluid slows down, rather than running so many steps that the next frame takes even longer.
+    pub max_steps_per_frame: u32,
+    /// Real time that hasn't been stepped yet, in seconds.
+    accumulated: f32,
+}
+
+impl Default for FluidTimestep {
+    fn default() -> Self {
+        Self {
+            rate: 30.,
+            substeps: 1,
+            max_steps_per_frame: 4,
+            accumulated: 0.,
+        }
+    }
+}
+
+/// The fluid's own clock.
+/// Fluid systems use this rather than Time, because Time's delta is the length of the frame, not of the step.
+#[init]
+#[derive(Resource, Default)]
+pub struct FluidTime {
+    delta_seconds: f32,
+    substep: u32,
+    overstep_fraction: f32,
+}
+
+impl FluidTime {
+    /// The length of the current substep, in seconds.
+    pub fn delta_secs(&self) -> f32 {
+        self.delta_seconds
+    }
+
+    /// Which substep of the step is running, starting at 0.
+    pub fn substep(&self) -> u32 {
+        self.substep
+    }
+
+    /// How far real time is through the next step, from 0 to 1.
+    /// What is drawn is interpolated by this much between the last 2 steps, so that it moves smoothly even when the frame rate is higher than the step rate.
+    pub fn overstep_fraction(&self) -> f32 {
+        self.overstep_fraction
+    }
+}
+
+/// Runs every fluid step that real time has caught up to since the last frame.
+#[system(Update)]
+fn run_fluid_steps(world: &mut World) {
+    let frame_seconds = world.resource::<Time>().delta_secs();
+
+    let mut fluid_timestep = world.resource_mut::<FluidTimestep>();
+    let step_seconds = 1. / fluid_timestep.rate;
+    let substeps = fluid_timestep.substeps.max(1);
+
+    fluid_timestep.accumulated += frame_seconds;
+    let mut steps = (fluid_timestep.accumulated / step_seconds) as u32;
+    if steps > fluid_timestep.max_steps_per_frame {
+        // Whatever is left over is dropped, so that it doesn't have to be caught up on later.
+        steps = fluid_timestep.max_steps_per_frame;
+        fluid_timestep.accumulated %= step_seconds;
+    } else {
+        fluid_timestep.accumulated -= steps as f32 * step_seconds;
+    }
+    let overstep_fraction = fluid_timestep.accumulated / step_seconds;
+
+    for _ in 0..steps {
+        for substep in 0..substeps {
+            let mut fluid_time = world.resource_mut::<FluidTime>();
+            fluid_time.delta_seconds = step_seconds / substeps as f32;
+            fluid_time.substep = substep;
+
+            world.run_schedule(FluidUpdate);
+        }
+    }
+
+    world.resource_mut::<FluidTime>().overstep_fraction = overstep_fraction;
+}
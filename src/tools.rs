@@ -1,7 +1,7 @@
 use crate::prelude::*;
 
 pub mod prelude {
-    pub use super::{Tool, ToolBarHovered};
+    pub use super::{Tool, ToolBarHovered, button_colour, selection_buttons, text_button};
 }
 
 #[system(Update)]
@@ -20,13 +20,15 @@ fn target_camera(
 
 /// The selected tool.
 /// This does not include their settings. That is stored separately, so that settings can persist between tool changes.
+/// Each tool's button has the tool as a component.
 #[init]
-#[derive(Resource, Default, Clone, Copy, PartialEq)]
+#[derive(Resource, Component, Default, Clone, Copy, PartialEq)]
 pub enum Tool {
     None,
     #[default]
     Draw,
     Water,
+    Emitters,
 }
 
 #[init]
@@ -36,8 +38,47 @@ pub struct ToolBarHovered(pub bool);
 #[derive(Component)]
 struct Root;
 
-#[derive(Component)]
-struct ToolButton(Tool);
+/// A button with text on it, which every tool's ui is made of.
+pub fn text_button(text: impl Into<String>, asset_server: &AssetServer) -> impl Bundle {
+    (
+        Text::new(text),
+        Button,
+        Outline::new(Val::Percent(5.), Val::Percent(0.), Color::BLACK),
+        TextFont {
+            font: asset_server.load("fonts/domine.ttf"),
+            font_size: 25.,
+            ..default()
+        },
+    )
+}
+
+/// The background colour of a button.
+/// Selected buttons stay as dark as a pressed button.
+pub fn button_colour(interaction: &Interaction, selected: bool) -> Color {
+    match interaction {
+        Interaction::Pressed => Srgba::gray(0.1).into(),
+        Interaction::Hovered => Srgba::gray(0.2).into(),
+        Interaction::None if selected => Srgba::gray(0.1).into(),
+        Interaction::None => Srgba::gray(0.4).into(),
+    }
+}
+
+/// Buttons that choose between values, like the tool bar.
+/// Each button has the value it chooses as a component, and pressing it makes that the selected value.
+pub fn selection_buttons<T: Component + PartialEq + Clone>(
+    mut selected: Mut<T>,
+    mut buttons: Query<(&Interaction, &mut BackgroundColor, &T), With<Button>>,
+) {
+    buttons
+        .iter_mut()
+        .for_each(|(interaction, mut colour, value)| {
+            if *interaction == Interaction::Pressed {
+                selected.set_if_neq(value.clone());
+            }
+
+            colour.0 = button_colour(interaction, *selected == *value);
+        });
+}
 
 #[system(Update)]
 fn tool_bar_setup(
@@ -66,50 +107,27 @@ fn tool_bar_setup(
         ..default()
     }));
 
-    [("Draw", Tool::Draw), ("Water", Tool::Water)]
-        .into_iter()
-        .for_each(|(text, tool)| {
-            root.with_child((
-                Text::new(text),
-                ToolButton(tool),
-                Button,
-                Outline::new(Val::Percent(5.), Val::Percent(0.), Color::BLACK),
-                TextFont {
-                    font: asset_server.load("fonts/domine.ttf"),
-                    font_size: 25.,
-                    ..default()
-                },
-            ));
-        });
+    [
+        ("Draw", Tool::Draw),
+        ("Water", Tool::Water),
+        ("Emitters", Tool::Emitters),
+    ]
+    .into_iter()
+    .for_each(|(text, tool)| {
+        root.with_child((text_button(text, &asset_server), tool));
+    });
 }
 
 #[system(Update)]
 fn tool_bar(
-    mut tool: ResMut<Tool>,
-    mut buttons: Query<(&Interaction, &mut BackgroundColor, &ToolButton), With<Button>>,
+    tool: ResMut<Tool>,
+    buttons: Query<(&Interaction, &mut BackgroundColor, &Tool), With<Button>>,
     mut tool_bar_hovered: ResMut<ToolBarHovered>,
 ) {
-    tool_bar_hovered.0 = false;
-    buttons
-        .iter_mut()
-        .for_each(|(interaction, mut colour, tool_button)| match interaction {
-            Interaction::Pressed => {
-                tool_bar_hovered.0 = true;
-                colour.0 = Srgba::gray(0.1).into();
-                *tool = tool_button.0;
-            }
-            Interaction::Hovered => {
-                tool_bar_hovered.0 = true;
-                colour.0 = Srgba::gray(0.2).into();
-            }
-            Interaction::None => {
-                if *tool == tool_button.0 {
-                    colour.0 = Srgba::gray(0.1).into();
-                } else {
-                    colour.0 = Srgba::gray(0.4).into();
-                }
-            }
-        });
+    tool_bar_hovered.0 = buttons
+        .iter()
+        .any(|(interaction, ..)| !matches!(interaction, Interaction::None));
+    selection_buttons(tool.into(), buttons);
 }
 
 #[system(Update)]
@@ -1,15 +1,47 @@
 use crate::prelude::*;
+use bevy::render::view::NoFrustumCulling;
+
+mod mesh;
 
 #[derive(Resource)]
 struct Settings {
-    colour: Color,
+    /// What is poured.
+    liquid: Liquid,
+    model: WaterModel,
+    /// How SPH water behaves.
+    sph_preset: SphPreset,
+    /// The most water particles there can be at once.
+    /// Pouring stops once there are this many.
+    max_particles: usize,
+    /// How many particles are poured per second.
+    particles_per_second: f32,
+    /// Draws every particle, as well as the water they make up.
+    draw_particles: bool,
 }
 app!(|app| {
     app.insert_resource(Settings {
-        colour: Srgba::new(0., 0., 1., 0.3).into(),
+        liquid: Liquid::FreshWater,
+        model: WaterModel::Flip,
+        sph_preset: SphPreset::Water,
+        max_particles: 2000,
+        particles_per_second: 60.,
+        draw_particles: false,
     });
 });
 
+/// How poured water is simulated.
+/// Each model's button has the model as a component.
+#[derive(Component, Clone, Copy, PartialEq)]
+enum WaterModel {
+    /// Particles that move with the grid.
+    Flip,
+    /// Particles that push on each other.
+    Sph,
+}
+
+/// In radians.
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
 #[system(Update)]
 fn spawn(
     tool: Res<Tool>,
@@ -17,8 +49,18 @@ fn spawn(
     cursor_translation: Res<CursorTranslation>,
     mut commands: Commands,
     settings: Res<Settings>,
+    sph_settings: Res<SphSettings>,
     tool_bar_hovered: Res<ToolBarHovered>,
+    // Hidden buttons are never interacted with, so these are only this tool's buttons and the tool bar's.
+    buttons: Query<&Interaction, With<Button>>,
     asset_server: Res<AssetServer>,
+    mut add_dye: EventWriter<AddDye>,
+    mut push_fluid: EventWriter<PushFluid>,
+    particles: Query<(), With<WaterParticle>>,
+    pooled: Query<Entity, With<Pooled>>,
+    time: Res<Time>,
+    mut spawned: Local<u32>,
+    mut owed: Local<f32>,
 ) {
     if !matches!(*tool, Tool::Water) {
         return;
@@ -28,6 +70,13 @@ fn spawn(
         return;
     }
 
+    if buttons
+        .iter()
+        .any(|interaction| !matches!(interaction, Interaction::None))
+    {
+        return;
+    }
+
     if !actions.pressed(&Action::Use) {
         return;
     }
@@ -35,226 +84,352 @@ fn spawn(
     let Some(cursor_translation) = &cursor_translation.0 else {
         return;
     };
-    let translation = cursor_translation.translation;
 
-    commands
-        .spawn((
-            Fluid::default(),
-            RigidBody::Dynamic,
-            Collider::circle(10.),
+    // Water lands with a splash when pouring starts.
+    if actions.just_pressed(&Action::Use) {
+        push_fluid.send(PushFluid {
+            translation: cursor_translation.translation,
+            radius: 60.,
+            push: Push::Impulse(Vec2::new(0., -100.)),
+        });
+    }
+
+    // Particles are owed at the spawn rate, and whole ones are poured as they add up.
+    *owed += settings.particles_per_second * time.delta_secs();
+    let count = (*owed as usize).min(
+        settings
+            .max_particles
+            .saturating_sub(particles.iter().len()),
+    );
+    *owed = owed.fract();
+    if count == 0 {
+        return;
+    }
+
+    add_dye.send(AddDye {
+        window: cursor_translation.window,
+        translation: cursor_translation.translation,
+        colour: settings.liquid.colour().into(),
+    });
+    // Pouring water pushes the water that is already there.
+    push_fluid.send(PushFluid {
+        translation: cursor_translation.translation,
+        radius: 30.,
+        push: Push::Force(Vec2::new(0., -300.)),
+    });
+
+    let mut pooled = pooled.iter();
+    (0..count).for_each(|_| {
+        // Particles are spread around the cursor, as particles exactly on top of each other can't push each other apart.
+        // Turning by the golden angle each time spreads them out evenly.
+        // The offset grows with the spacing, so particles start out just as squeezed together whichever preset is used.
+        *spawned = spawned.wrapping_add(1);
+        let translation = cursor_translation.translation
+            + Vec2::from_angle(*spawned as f32 * GOLDEN_ANGLE) * sph_settings.spacing * 0.15;
+
+        let mut particle = match pooled.next() {
+            Some(entity) => {
+                let mut particle = commands.entity(entity);
+                particle.remove::<Pooled>();
+                particle
+            }
+            None => commands.spawn_empty(),
+        };
+
+        particle.insert((
+            WaterParticle::new(translation, Vec2::ZERO, settings.liquid),
             Transform::from_translation(Vec3::new(translation.x, translation.y, 0.)),
+            particle_visibility(&settings),
             Sprite {
                 image: asset_server.load("brushes/circle.png"),
-                color: settings.colour,
+                color: settings.liquid.colour(),
                 custom_size: Some(Vec2::splat(20.)),
                 ..default()
             },
-            Friction {
-                dynamic_coefficient: 0.,
-                static_coefficient: 0.,
-                ..default()
-            },
-            LockedAxes::ROTATION_LOCKED,
-            CollisionLayers::new(Layer::Fluid, Layer::Default),
-        ))
-        .with_child((Sensor, Collider::circle(H), CollidingEntities::default()));
-}
+        ));
 
-#[derive(PhysicsLayer, Default)]
-enum Layer {
-    #[default]
-    Default,
-    Fluid,
+        if settings.model == WaterModel::Sph {
+            particle.insert(SphParticle::default());
+        }
+    });
 }
 
-// All the fluid physics is taken from https://www.cs.cornell.edu/~bindel/class/cs5220-f11/code/sph.pdf
-/*
-char* fname; /* File name */
-int nframes; /* Number of frames */
-int npframe; /* Steps per frame */
-float h; /* Particle size */
-float dt; /* Time step */
-float rho0; /* Reference density */
-float k; /* Bulk modulus */
-float mu; /* Viscosity */
-float g; /* Gravity strength */
-
-int n; /* Number of particles */
-float mass; /* Particle mass */
-float* restrict rho; /* Densities */
-float* restrict x; /* Positions */
-float* restrict vh; /* Velocities (half step) */
-float* restrict v; /* Velocities (full step) */
-float* restrict a; /* Acceleration */
-
-static void default_params(sim_param_t* params)
-{
-params->fname = "run.out";
-params->nframes = 400;
-params->npframe = 100;
-params->dt = 1e-4;
-params->h = 5e-2;
-params->rho0 = 1000;
-params->k = 1e3;
-params->mu = 0.1;
-params->g = 9.8;
+fn particle_visibility(settings: &Settings) -> Visibility {
+    if settings.draw_particles {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    }
 }
-*/
-
-// Unsure.
-const MASS: f32 = 10.;
-
-// Unsure.
-const H: f32 = 30.;
-const H2: f32 = H * H;
-const H8: f32 = (H2 * H2) * (H2 * H2);
-
-const C: f32 = 4. * MASS / std::f32::consts::PI / H8;
-const C0: f32 = MASS / std::f32::consts::PI / (H2 * H2);
-const CP: f32 = 15. * K;
-const CV: f32 = -40. * MU;
-
-// Unsure.
-const RHO0: f32 = 5.;
-// Unsure.
-const K: f32 = 1000.;
-// Unsure.
-const MU: f32 = 0.1;
-
-#[derive(Component, Default)]
-#[require(AccelerationAccumulator)]
-struct Fluid {
-    // Density.
-    rho: f32,
+
+/// Shows or hides the particles, when they are turned on or off.
+#[system(Update)]
+fn draw_particles(
+    settings: Res<Settings>,
+    mut particles: Query<&mut Visibility, With<WaterParticle>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    particles.iter_mut().for_each(|mut visibility| {
+        *visibility = particle_visibility(&settings);
+    });
 }
 
-#[derive(Component, Default)]
-struct AccelerationAccumulator(Vec2);
+/// The mesh that all of a liquid is drawn with.
+#[derive(Component)]
+struct WaterMesh(Liquid);
 
-//#[system(Update::Fluid::Pressure)]
-fn pressure(
-    mut particles: Query<(&mut Fluid, &Transform, &Children)>,
-    collisions: Query<&CollidingEntities>,
-    colliders: Query<&Transform, With<Fluid>>,
+#[system(Startup)]
+fn create_water_meshes(
+    meshes: Res<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut commands: Commands,
 ) {
-    particles
-        .par_iter_mut()
-        .for_each(|(mut fluid, transform, children)| {
-            // All fluid particles must have 1 child containing the density sensor.
-            let child = children.first().unwrap();
-
-            let Ok(collisions) = collisions.get(*child) else {
-                error!("Child could not be got.");
-                return;
-            };
-
-            fluid.rho = 0.;
-            collisions.0.iter().for_each(|collision| {
-                let Ok(collider_transform) = colliders.get(*collision) else {
-                    return;
-                };
-
-                let dx = transform.translation.x - collider_transform.translation.x;
-                let dy = transform.translation.y - collider_transform.translation.y;
-
-                let r2 = dx * dx + dy * dy;
-                let z = H2 - r2;
-                if z > 0. {
-                    let rho_ij = C * z * z * z;
-                    // We can only do one of the 2 operations. Consider doubling the value, to make up for the missed return stroke?
-                    fluid.rho += rho_ij * 2.;
-                }
-            });
-            //info!("rho: {}", fluid.rho)
-        });
+    Liquid::ALL.into_iter().for_each(|liquid| {
+        commands.spawn((
+            WaterMesh(liquid),
+            // The mesh is added once there is some of the liquid.
+            Mesh2d(meshes.reserve_handle()),
+            MeshMaterial2d(materials.add(ColorMaterial::from_color(liquid.colour()))),
+            // The mesh changes every frame, so its bounds would be out of date.
+            NoFrustumCulling,
+            Visibility::Hidden,
+        ));
+    });
 }
 
-//#[system(Update::Fluid::GetAcceleration)]
-fn get_acceleration(
-    mut particles: Query<(
-        &mut AccelerationAccumulator,
-        &LinearVelocity,
-        &Fluid,
-        &Transform,
-        &Children,
-    )>,
-    collisions: Query<&CollidingEntities>,
-    colliders: Query<(&Fluid, &Transform, &LinearVelocity)>,
+/// Rebuilds each liquid's mesh around wherever its particles are drawn.
+#[system(Update)]
+fn draw_water(
+    particles: Query<(&Transform, &WaterParticle)>,
+    mut water_meshes: Query<(&WaterMesh, &Mesh2d, &mut Visibility)>,
+    mut meshes: ResMut<Assets<Mesh>>,
 ) {
-    particles.par_iter_mut().for_each(
-        |(mut acceleration_accumulator, velocity, fluid, transform, children)| {
-            // All fluid particles must have 1 child containing the density sensor.
-            let child = children.first().unwrap();
-
-            let Ok(collisions) = collisions.get(*child) else {
-                error!("Child could not be got.");
-                return;
-            };
-
-            acceleration_accumulator.0 = Vec2::ZERO;
-            collisions.0.iter().for_each(|collision| {
-                let Ok((collider_fluid, collider_transform, collider_velocity)) =
-                    colliders.get(*collision)
-                else {
-                    return;
-                };
-
-                let dx = transform.translation.x - collider_transform.translation.x;
-                let dy = transform.translation.y - collider_transform.translation.y;
-
-                let r2 = dx * dx + dy * dy;
-                if r2 < H2 {
-                    let q = r2.sqrt() / H;
-                    let u = 1. - q;
-                    let w0 = C0 * u / fluid.rho / collider_fluid.rho;
-                    let wp = w0 * CP * (fluid.rho + collider_fluid.rho - (2. * RHO0)) * u / q;
-                    let wv = w0 * CV;
-
-                    let dvx = velocity.x - collider_velocity.x;
-                    let dvy = velocity.y - collider_velocity.y;
-
-                    let acceleration = Vec2::new(wp * dx + wv * dvx, wp * dy + wv * dvy);
-                    acceleration_accumulator.0 +=
-                        acceleration.clamp(Vec2::splat(-100.), Vec2::splat(100.));
+    water_meshes
+        .iter_mut()
+        .for_each(|(water_mesh, mesh, mut visibility)| {
+            let translations: Vec<Vec2> = particles
+                .iter()
+                .filter(|(_, particle)| particle.liquid == water_mesh.0)
+                .map(|(transform, _)| transform.translation.truncate())
+                .collect();
+
+            match mesh::water_mesh(&translations) {
+                Some(liquid_mesh) => {
+                    meshes.insert(&mesh.0, liquid_mesh);
+                    *visibility = Visibility::Inherited;
                 }
-            });
-            //info!("acceleration_delta: {}", acceleration_accumulator.0);
-        },
-    );
+                None => *visibility = Visibility::Hidden,
+            }
+        });
 }
 
-//#[system(Update::Fluid::ApplyAcceleration)]
-fn apply_acceleration(
-    mut particles: Query<(&mut LinearVelocity, &AccelerationAccumulator)>,
-    time: Res<Time>,
+/// Pools particles that have left every grid, as they have fallen off every monitor and can't come back.
+#[system(Update)]
+fn pool_lost(
+    particles: Query<(Entity, &WaterParticle)>,
+    grids: Query<&Grid>,
+    mut commands: Commands,
 ) {
-    let time_delta_seconds = time.delta_secs();
+    // Grids are rebuilt when windows change, and particles shouldn't be lost while there are none.
+    if grids.is_empty() {
+        return;
+    }
+
     particles
-        .par_iter_mut()
-        .for_each(|(mut velocity, acceleration_accumulator)| {
-            velocity.0 += acceleration_accumulator.0 * time_delta_seconds;
+        .iter()
+        .filter(|(_, particle)| {
+            !grids
+                .iter()
+                .any(|grid| grid.region().contains(particle.translation))
+        })
+        .for_each(|(entity, _)| Pooled::pool(commands.entity(entity)));
+}
+
+#[derive(Component)]
+struct Root;
+
+/// Turns drawing particles on and off.
+#[derive(Component)]
+struct ParticlesButton;
+
+/// Shows how the last pressure solve went, for tuning PressureSolver.
+#[derive(Component)]
+struct SolveText;
+
+/// Shows the fluid under the cursor.
+#[derive(Component)]
+struct ProbeText;
+
+#[system(Update)]
+fn ui(
+    cursor_translation: Res<CursorTranslation>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    mut finished: Local<bool>,
+) {
+    if *finished {
+        return;
+    }
+
+    let Some(cursor_translation) = &cursor_translation.0 else {
+        return;
+    };
+
+    *finished = true;
+
+    let mut root = commands.spawn((Root, TargetCamera(cursor_translation.window), Node {
+        display: Display::Flex,
+        flex_direction: FlexDirection::Column,
+        align_items: AlignItems::Start,
+        justify_content: JustifyContent::Center,
+        width: Val::Percent(100.),
+        height: Val::Percent(100.),
+        ..default()
+    }));
+
+    [("FLIP", WaterModel::Flip), ("SPH", WaterModel::Sph)]
+        .into_iter()
+        .for_each(|(text, model)| {
+            root.with_child((text_button(text, &asset_server), model));
         });
+
+    SphPreset::ALL.into_iter().for_each(|preset| {
+        root.with_child((text_button(preset.name(), &asset_server), preset));
+    });
+
+    Liquid::ALL.into_iter().for_each(|liquid| {
+        root.with_child((text_button(liquid.name(), &asset_server), liquid));
+    });
+
+    root.with_child((text_button("Particles", &asset_server), ParticlesButton));
+
+    root.with_child((Text::default(), SolveText, TextFont {
+        font: asset_server.load("fonts/domine.ttf"),
+        font_size: 15.,
+        ..default()
+    }));
+
+    root.with_child((Text::default(), ProbeText, TextFont {
+        font: asset_server.load("fonts/domine.ttf"),
+        font_size: 15.,
+        ..default()
+    }));
 }
 
-#[derive(Default)]
-struct One {
-    blah: f32,
+#[system(Update)]
+fn solve_text(report: Res<PressureSolveReport>, text: Option<Single<&mut Text, With<SolveText>>>) {
+    let Some(mut text) = text else {
+        return;
+    };
+
+    text.0 = format!(
+        "Pressure solve: {} iterations, {:.1e} residual",
+        report.iterations, report.residual
+    );
 }
 
-#[derive(Default)]
-struct Two {
-    foo: f32,
+#[system(Update)]
+fn probe_text(
+    cursor_translation: Res<CursorTranslation>,
+    fluid_sampler: FluidSampler,
+    text: Option<Single<&mut Text, With<ProbeText>>>,
+) {
+    let Some(mut text) = text else {
+        return;
+    };
+
+    let Some(cursor_translation) = &cursor_translation.0 else {
+        text.0.clear();
+        return;
+    };
+
+    let translation = cursor_translation.translation;
+    text.0 = match (
+        fluid_sampler.solid(translation),
+        fluid_sampler.velocity(translation),
+        fluid_sampler.pressure(translation),
+        fluid_sampler.temperature(translation),
+    ) {
+        (Some(false), Some(velocity), Some(pressure), Some(temperature)) => format!(
+            "Speed: {:.0}, pressure: {:.1e}, temperature: {:.1}°C",
+            velocity.length(),
+            pressure,
+            temperature
+        ),
+        (Some(true), ..) => "Solid".to_string(),
+        // The cursor isn't over a grid.
+        _ => String::new(),
+    };
+}
+
+#[system(Update)]
+fn model_buttons(
+    settings: ResMut<Settings>,
+    buttons: Query<(&Interaction, &mut BackgroundColor, &WaterModel), With<Button>>,
+) {
+    selection_buttons(
+        settings.map_unchanged(|settings| &mut settings.model),
+        buttons,
+    );
+}
+
+#[system(Update)]
+fn preset_buttons(
+    mut settings: ResMut<Settings>,
+    mut sph_settings: ResMut<SphSettings>,
+    buttons: Query<(&Interaction, &mut BackgroundColor, &SphPreset), With<Button>>,
+) {
+    let preset = settings.sph_preset;
+    selection_buttons(
+        settings
+            .reborrow()
+            .map_unchanged(|settings| &mut settings.sph_preset),
+        buttons,
+    );
+
+    if settings.sph_preset != preset {
+        *sph_settings = settings.sph_preset.into();
+    }
 }
 
-fn experiment() {
-    let mut foo = [
-        (One::default(), Two::default()),
-        (One::default(), Two::default()),
-    ];
+#[system(Update)]
+fn liquid_buttons(
+    settings: ResMut<Settings>,
+    buttons: Query<(&Interaction, &mut BackgroundColor, &Liquid), With<Button>>,
+) {
+    selection_buttons(
+        settings.map_unchanged(|settings| &mut settings.liquid),
+        buttons,
+    );
+}
+
+#[system(Update)]
+fn particles_button(
+    mut settings: ResMut<Settings>,
+    mut buttons: Query<(Ref<Interaction>, &mut BackgroundColor), With<ParticlesButton>>,
+) {
+    buttons.iter_mut().for_each(|(interaction, mut colour)| {
+        // Only toggled once per press, not every frame it is held.
+        if *interaction == Interaction::Pressed && interaction.is_changed() {
+            settings.draw_particles = !settings.draw_particles;
+        }
 
-    let one = &mut foo[0].0;
-    let two = &foo[1].1;
+        colour.0 = button_colour(&interaction, settings.draw_particles);
+    });
+}
 
-    info!("{}", one.blah);
-    info!("{}", two.foo);
+#[system(Update)]
+fn ui_visibility(tool: Res<Tool>, visibility: Option<Single<&mut Visibility, With<Root>>>) {
+    let Some(mut visibility) = visibility else {
+        return;
+    };
+
+    if matches!(*tool, Tool::Water) {
+        **visibility = Visibility::Visible;
+    } else {
+        **visibility = Visibility::Hidden;
+    }
 }
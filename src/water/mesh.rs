@@ -0,0 +1,333 @@
+// Water is drawn as 1 mesh, made by blending the particles together into metaballs, then tracing around them with marching squares.
+// This is all on the CPU, and doesn't touch the world, so it can be run on its own.
+
+use crate::prelude::*;
+use bevy::render::{
+    mesh::{Indices, PrimitiveTopology},
+    render_asset::RenderAssetUsages,
+};
+
+/// How far each particle's metaball reaches, in world units.
+const RADIUS: f32 = 20.;
+/// How far apart the metaball field is sampled, in world units.
+/// Smaller is smoother, but slower.
+const SPACING: f32 = 5.;
+/// The surface is drawn where the field is this strong.
+/// A lone particle's field peaks at 1, so at 0.5 it is drawn about half of RADIUS wide.
+const THRESHOLD: f32 = 0.5;
+
+/// How strongly the particles are felt, sampled on a grid.
+pub(super) struct MetaballField {
+    /// The translation of the first sample.
+    origin: Vec2,
+    /// The number of samples across and up.
+    size: UVec2,
+    values: Vec<f32>,
+}
+
+impl MetaballField {
+    /// Samples the field around the particles, with a margin that is always below the threshold, so that the surface is closed.
+    pub(super) fn new(particles: &[Vec2]) -> Option<Self> {
+        let (min, max) = particles.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), translation| (min.min(*translation), max.max(*translation)),
+        );
+        if !min.is_finite() || !max.is_finite() {
+            return None;
+        }
+
+        let origin = min - RADIUS - SPACING;
+        let size = ((max + RADIUS + SPACING - origin) / SPACING)
+            .ceil()
+            .as_uvec2()
+            + 1;
+        let mut values = vec![0.; (size.x * size.y) as usize];
+
+        particles.iter().for_each(|translation| {
+            let start = ((translation - RADIUS - origin) / SPACING)
+                .floor()
+                .as_uvec2();
+            let end = ((translation + RADIUS - origin) / SPACING)
+                .ceil()
+                .as_uvec2()
+                .min(size - 1);
+
+            (start.y..=end.y).for_each(|y| {
+                (start.x..=end.x).for_each(|x| {
+                    let sample = origin + UVec2::new(x, y).as_vec2() * SPACING;
+                    let q2 = sample.distance_squared(*translation) / (RADIUS * RADIUS);
+                    if q2 < 1. {
+                        values[(y * size.x + x) as usize] += (1. - q2) * (1. - q2);
+                    }
+                });
+            });
+        });
+
+        Some(Self {
+            origin,
+            size,
+            values,
+        })
+    }
+
+    fn value(&self, coordinates: UVec2) -> f32 {
+        self.values[(coordinates.y * self.size.x + coordinates.x) as usize]
+    }
+
+    fn translation(&self, coordinates: UVec2) -> Vec2 {
+        self.origin + coordinates.as_vec2() * SPACING
+    }
+
+    /// Traces the surface with marching squares, and fills it in.
+    /// Returns the positions and indices of a triangle list, wound anticlockwise.
+    pub(super) fn triangles(&self) -> (Vec<Vec2>, Vec<u32>) {
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+
+        let mut add_polygon = |polygon: &[Vec2]| {
+            // Every polygon marching squares makes is convex, so it can be filled as a fan.
+            let first = positions.len() as u32;
+            positions.extend_from_slice(polygon);
+            (1..polygon.len() as u32 - 1).for_each(|index| {
+                indices.extend_from_slice(&[first, first + index, first + index + 1]);
+            });
+        };
+
+        (0..self.size.y - 1).for_each(|y| {
+            (0..self.size.x - 1).for_each(|x| {
+                // Anticlockwise from the bottom left.
+                let corners = [
+                    UVec2::new(x, y),
+                    UVec2::new(x + 1, y),
+                    UVec2::new(x + 1, y + 1),
+                    UVec2::new(x, y + 1),
+                ];
+                let values = corners.map(|corner| self.value(corner));
+                let translations = corners.map(|corner| self.translation(corner));
+                let inside = values.map(|value| value >= THRESHOLD);
+
+                if !inside.contains(&true) {
+                    return;
+                }
+                if !inside.contains(&false) {
+                    add_polygon(&translations);
+                    return;
+                }
+
+                // Where the surface crosses the edge from each corner to the next.
+                let crossing = |corner: usize| {
+                    let next = (corner + 1) % 4;
+                    let t = (THRESHOLD - values[corner]) / (values[next] - values[corner]);
+                    translations[corner].lerp(translations[next], t)
+                };
+
+                // Only opposite corners are inside, so the middle decides whether they are joined.
+                let saddle = inside[0] == inside[2] && inside[1] == inside[3];
+                if saddle && values.iter().sum::<f32>() / 4. < THRESHOLD {
+                    (0..4).filter(|corner| inside[*corner]).for_each(|corner| {
+                        let previous = (corner + 3) % 4;
+                        add_polygon(&[translations[corner], crossing(corner), crossing(previous)]);
+                    });
+                    return;
+                }
+
+                // Walking around the square, keeping the corners that are inside and the crossings between, gives the filled polygon.
+                let polygon: Vec<Vec2> = (0..4)
+                    .flat_map(|corner| {
+                        let next = (corner + 1) % 4;
+                        let kept_corner = inside[corner].then(|| translations[corner]);
+                        let kept_crossing =
+                            (inside[corner] != inside[next]).then(|| crossing(corner));
+                        [kept_corner, kept_crossing]
+                    })
+                    .flatten()
+                    .collect();
+                add_polygon(&polygon);
+            });
+        });
+
+        (positions, indices)
+    }
+}
+
+/// Makes a mesh of the water around the particles.
+/// Returns none if there is no water to draw.
+pub(super) fn water_mesh(particles: &[Vec2]) -> Option<Mesh> {
+    let (positions, indices) = MetaballField::new(particles)?.triangles();
+    if indices.is_empty() {
+        return None;
+    }
+
+    let positions: Vec<[f32; 3]> = positions
+        .into_iter()
+        .map(|position| [position.x, position.y, 0.])
+        .collect();
+
+    Some(
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_indices(Indices::U32(indices)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Positions from neighbouring squares are worked out separately, so they are rounded before being compared.
+    fn key(position: Vec2) -> IVec2 {
+        (position * 1024.).round().as_ivec2()
+    }
+
+    fn triangles(particles: &[Vec2]) -> (Vec<Vec2>, Vec<u32>) {
+        let (positions, indices) = MetaballField::new(particles).unwrap().triangles();
+        assert_eq!(indices.len() % 3, 0);
+        assert!(
+            indices
+                .iter()
+                .all(|index| (*index as usize) < positions.len())
+        );
+        (positions, indices)
+    }
+
+    /// The edges of the triangles that aren't shared with another triangle.
+    fn outline(positions: &[Vec2], indices: &[u32]) -> Vec<(Vec2, Vec2)> {
+        let edges: Vec<(Vec2, Vec2)> = indices
+            .chunks_exact(3)
+            .flat_map(|triangle| {
+                (0..3).map(move |corner| {
+                    (
+                        positions[triangle[corner] as usize],
+                        positions[triangle[(corner + 1) % 3] as usize],
+                    )
+                })
+            })
+            .collect();
+        let mut counts: HashMap<(IVec2, IVec2), i32> = HashMap::default();
+        edges.iter().for_each(|(start, end)| {
+            *counts.entry((key(*start), key(*end))).or_default() += 1;
+        });
+
+        edges
+            .into_iter()
+            .filter(|(start, end)| !counts.contains_key(&(key(*end), key(*start))))
+            .collect()
+    }
+
+    /// How many separate pieces the triangles make, counting triangles that share a corner as 1 piece.
+    fn pieces(positions: &[Vec2], indices: &[u32]) -> usize {
+        let triangles: Vec<&[u32]> = indices.chunks_exact(3).collect();
+        let mut touching: HashMap<IVec2, Vec<usize>> = HashMap::default();
+        triangles
+            .iter()
+            .enumerate()
+            .for_each(|(triangle, corners)| {
+                corners.iter().for_each(|corner| {
+                    touching
+                        .entry(key(positions[*corner as usize]))
+                        .or_default()
+                        .push(triangle);
+                });
+            });
+
+        let mut visited = vec![false; triangles.len()];
+        (0..triangles.len())
+            .filter(|start| {
+                if visited[*start] {
+                    return false;
+                }
+
+                visited[*start] = true;
+                let mut stack = vec![*start];
+                while let Some(triangle) = stack.pop() {
+                    triangles[triangle].iter().for_each(|corner| {
+                        touching[&key(positions[*corner as usize])]
+                            .iter()
+                            .for_each(|neighbour| {
+                                if !visited[*neighbour] {
+                                    visited[*neighbour] = true;
+                                    stack.push(*neighbour);
+                                }
+                            });
+                    });
+                }
+                true
+            })
+            .count()
+    }
+
+    #[test]
+    fn no_particles_no_mesh() {
+        assert!(water_mesh(&[]).is_none());
+    }
+
+    #[test]
+    fn one_particle_is_closed_around_it() {
+        let particle = Vec2::new(130., -45.);
+        let (positions, indices) = triangles(&[particle]);
+
+        assert!(!indices.is_empty());
+        assert!(water_mesh(&[particle]).is_some());
+        assert!(
+            positions
+                .iter()
+                .all(|position| position.distance(particle) <= RADIUS)
+        );
+        assert_eq!(pieces(&positions, &indices), 1);
+
+        // Where a lone particle's field is at the threshold.
+        let surface_distance = RADIUS * (1. - THRESHOLD.sqrt()).sqrt();
+        let outline = outline(&positions, &indices);
+        assert!(!outline.is_empty());
+        // Every edge that isn't shared is on the surface, so none were cut off by the edge of the field.
+        outline.iter().for_each(|(start, end)| {
+            [start, end].into_iter().for_each(|position| {
+                let distance = position.distance(particle);
+                assert!(
+                    (distance - surface_distance).abs() < SPACING / 2.,
+                    "{position} is {distance} from the particle"
+                );
+            });
+        });
+        // The outline goes all the way around, so every point on it is left as often as it is reached.
+        let mut ends: HashMap<IVec2, i32> = HashMap::default();
+        outline.iter().for_each(|(start, end)| {
+            *ends.entry(key(*start)).or_default() += 1;
+            *ends.entry(key(*end)).or_default() -= 1;
+        });
+        assert!(ends.values().all(|count| *count == 0));
+    }
+
+    #[test]
+    fn clusters_far_apart_are_separate() {
+        let clusters = [Vec2::new(0., 0.), Vec2::new(200., 50.)];
+        let particles: Vec<Vec2> = clusters
+            .iter()
+            .flat_map(|cluster| {
+                [Vec2::ZERO, Vec2::X, Vec2::Y, Vec2::ONE].map(|offset| *cluster + offset * 8.)
+            })
+            .collect();
+        let (positions, indices) = triangles(&particles);
+
+        assert_eq!(pieces(&positions, &indices), 2);
+        // Every triangle belongs to 1 cluster.
+        indices.chunks_exact(3).for_each(|triangle| {
+            let closest: Vec<usize> = triangle
+                .iter()
+                .map(|corner| {
+                    clusters
+                        .iter()
+                        .map(|cluster| cluster.distance(positions[*corner as usize]))
+                        .enumerate()
+                        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                        .unwrap()
+                        .0
+                })
+                .collect();
+            assert!(closest.iter().all(|cluster| *cluster == closest[0]));
+        });
+    }
+}
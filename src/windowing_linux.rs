@@ -52,6 +52,33 @@ enum WindowingState {
 #[derive(Event)]
 pub struct WindowingDone;
 
+/// The monitor that a window was created for.
+#[derive(Component)]
+struct ForMonitor(Entity);
+
+fn spawn_window(commands: &mut Commands, monitor: Entity, render_layer: usize) {
+    let mut window = commands.spawn_empty();
+    let window_entity = window.id();
+
+    // Putting the window and the camera together is useful for the grid.
+    window.insert((
+        Window {
+            mode: WindowMode::Windowed,
+            position: WindowPosition::Centered(MonitorSelection::Entity(monitor)),
+            transparent: true,
+            ..default()
+        },
+        Camera2d,
+        // Layer 0 is seen by every window, so things that move around the world, like water, show up on whichever monitor they are on.
+        RenderLayers::from_layers(&[0, render_layer]),
+        Camera {
+            target: RenderTarget::Window(WindowRef::Entity(window_entity)),
+            ..default()
+        },
+        ForMonitor(monitor),
+    ));
+}
+
 #[system(Update)]
 fn create_windows_x11(
     primary_window: Option<Single<Entity, With<PrimaryWindow>>>,
@@ -78,32 +105,113 @@ fn create_windows_x11(
     commands.entity(*primary_window).despawn();
 
     monitors.iter().enumerate().for_each(|(index, monitor)| {
-        let mut window = commands.spawn_empty();
-        let window_entity = window.id();
-
-        // Putting the window and the camera together is useful for the grid.
-        window.insert((
-            Window {
-                mode: WindowMode::Windowed,
-                position: WindowPosition::Centered(MonitorSelection::Entity(monitor)),
-                transparent: true,
-                ..default()
-            },
-            Camera2d,
-            RenderLayers::layer(index),
-            Camera {
-                target: RenderTarget::Window(WindowRef::Entity(window_entity)),
-                ..default()
-            },
-        ));
+        spawn_window(&mut commands, monitor, index);
     });
 
     *windowing_state = WindowingState::ConfigureWindows;
 }
 
+/// Creates windows for monitors that are plugged in after setup, and removes windows for monitors that are unplugged.
+/// New windows go back through the usual setup, so WindowingDone is sent again once they are ready.
+#[system(Update)]
+fn monitor_hotplug_x11(
+    new_monitors: Query<Entity, Added<Monitor>>,
+    mut removed_monitors: RemovedComponents<Monitor>,
+    windows: Query<(Entity, &ForMonitor, &RenderLayers)>,
+    mut commands: Commands,
+    wayland: Option<Res<Wayland>>,
+    mut windowing_state: ResMut<WindowingState>,
+) {
+    // Monitors that exist during setup already get windows.
+    if !matches!(*windowing_state, WindowingState::Done) {
+        removed_monitors.clear();
+        return;
+    }
+
+    let Some(wayland) = wayland else {
+        return;
+    };
+    if wayland.0 {
+        return;
+    }
+
+    removed_monitors.read().for_each(|monitor| {
+        windows
+            .iter()
+            .filter(|(_, for_monitor, _)| for_monitor.0 == monitor)
+            .for_each(|(window_entity, _, _)| {
+                info!("Monitor removed, so its window was too.");
+                commands.entity(window_entity).despawn_recursive();
+            });
+    });
+
+    // Every window needs its own render layer.
+    let mut next_render_layer = windows
+        .iter()
+        .flat_map(|(_, _, render_layers)| render_layers.iter())
+        .max()
+        .map_or(0, |render_layer| render_layer + 1);
+
+    let mut any_new_monitors = false;
+    new_monitors.iter().for_each(|monitor| {
+        info!("Monitor added, so a window was created for it.");
+        spawn_window(&mut commands, monitor, next_render_layer);
+        next_render_layer += 1;
+        any_new_monitors = true;
+    });
+
+    if any_new_monitors {
+        *windowing_state = WindowingState::ConfigureWindows;
+    }
+}
+
+/// Moves each window's camera to where the window is on the desktop, so that all the windows together show 1 continuous world.
+/// World space is in logical pixels, with the origin at the top left of the desktop, and y pointing up.
+/// Monitors with different scale factors won't line up perfectly, as each window converts to logical pixels with its own scale factor.
+#[system(Update)]
+fn position_cameras_x11(
+    mut cameras: Query<(Entity, &mut Transform), (With<Camera2d>, With<Window>)>,
+    winit_windows: NonSend<WinitWindows>,
+    wayland: Option<Res<Wayland>>,
+) {
+    let Some(wayland) = wayland else {
+        return;
+    };
+    if wayland.0 {
+        return;
+    }
+
+    cameras
+        .iter_mut()
+        .for_each(|(window_entity, mut transform)| {
+            let Some(window) = winit_windows.get_window(window_entity) else {
+                return;
+            };
+
+            let Ok(position) = window.inner_position() else {
+                return;
+            };
+            let scale_factor = window.scale_factor();
+            let position = position.to_logical::<f32>(scale_factor);
+            let size = window.inner_size().to_logical::<f32>(scale_factor);
+
+            // Winit's y points down, but ours points up.
+            let centre = Vec2::new(
+                position.x + size.width / 2.,
+                -(position.y + size.height / 2.),
+            );
+
+            // Only changing it when it moves keeps change detection useful.
+            if transform.translation.xy() != centre {
+                transform.translation = centre.extend(transform.translation.z);
+            }
+        });
+}
+
 #[system(Update)]
 fn configure_windows_x11(
     mut windowing_state: ResMut<WindowingState>,
+    windows: Query<Entity, With<Window>>,
     winit_windows: NonSend<WinitWindows>,
     wayland: Option<Res<Wayland>>,
 ) {
@@ -118,8 +226,12 @@ fn configure_windows_x11(
         return;
     }
 
-    // If there are 0 or 1 windows, then we know something has gone wrong.
-    if winit_windows.windows.len() <= 1 {
+    // Winit creates windows a little after they are spawned, so we wait for all of them.
+    if windows.is_empty()
+        || windows
+            .iter()
+            .any(|window| winit_windows.get_window(window).is_none())
+    {
         return;
     }
 